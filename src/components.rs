@@ -1,9 +1,12 @@
 use bracket_lib::prelude::*;
+use serde::{Deserialize, Serialize};
+use specs::error::NoError;
 use specs::prelude::*;
+use specs::saveload::{ConvertSaveload, Marker};
 use std::convert::TryInto;
 use std::ops;
 
-#[derive(Component, Clone, Copy, PartialEq, Eq)]
+#[derive(Component, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
@@ -70,44 +73,218 @@ impl ops::Mul<Position> for Position {
 }
 
 /// Name of the entity
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Name {
     pub name: String,
 }
 
-/// Entities that should be rendered to the screen
-#[derive(Component)]
+/// Entities that should be rendered to the screen. Entities with a lower render order are drawn on
+/// top of those with a higher one.
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Renderable {
     pub glyph: u8,
     pub fg: RGB,
     pub bg: RGB,
+    pub render_order: i32,
 }
 
 /// Component that identifies the player character
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Player {}
 
+/// Set of indexes in the map. Checking if it contains an index takes constant time, and clearing it
+/// only takes as long as the number of indexes in it. It's saved as the list of indexes.
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(from = "Vec<usize>", into = "Vec<usize>")]
+pub struct TileSet {
+    tiles: Vec<usize>,
+    // One bit per map index, set for the indexes in the list
+    bits: Vec<u64>,
+}
+
+impl TileSet {
+    pub fn contains(&self, idx: usize) -> bool {
+        match self.bits.get(idx / 64) {
+            Some(word) => word & (1 << (idx % 64)) != 0,
+            None => false,
+        }
+    }
+
+    pub fn insert(&mut self, idx: usize) {
+        let (word, bit) = (idx / 64, 1 << (idx % 64));
+        if word >= self.bits.len() {
+            self.bits.resize(word + 1, 0);
+        }
+        if self.bits[word] & bit == 0 {
+            self.bits[word] |= bit;
+            self.tiles.push(idx);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for idx in self.tiles.iter() {
+            self.bits[idx / 64] = 0;
+        }
+        self.tiles.clear();
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, usize> {
+        self.tiles.iter()
+    }
+}
+
+impl From<Vec<usize>> for TileSet {
+    fn from(tiles: Vec<usize>) -> Self {
+        let mut set = Self::default();
+        for idx in tiles {
+            set.insert(idx);
+        }
+        set
+    }
+}
+
+impl From<TileSet> for Vec<usize> {
+    fn from(set: TileSet) -> Self {
+        set.tiles
+    }
+}
+
 /// Component for entities that can see things following a FOV algorithm.
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Vision {
-    pub visible: Vec<Position>,
+    /// Indexes in the map of the visible tiles
+    pub visible: TileSet,
     pub range: i32,
     pub recompute: bool,
 }
 
 /// Is controlled by monster AI (MonsterAISystem), that chases and attacks the player.
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Monster {}
 
+/// What a monster is doing, which decides how MonsterAISystem moves it
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Debug)]
+pub enum AiState {
+    /// Stays in place until it sees the player or gets bored
+    Idle,
+    /// Walks around at random
+    Wander,
+    /// Chases and attacks the player in sight
+    Hunt,
+    /// Lost sight of the player, and goes looking for them where they were last seen
+    Search,
+    /// Runs away from the player because it's badly hurt
+    Flee,
+}
+
+/// State of the monster AI, and what it remembers about the player
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Ai {
+    pub state: AiState,
+    pub last_known_player_pos: Option<Position>,
+    /// Turns left before giving up the search
+    pub search_turns: i32,
+}
+
 /// Whether this entity blocks other entities from occupying the same tile.
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Blocking {}
 
 /// Basic struct for entities that can fight and die
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Fighter {
     pub hp: i32,
     pub max_hp: i32,
     pub attack: i32,
     pub defense: i32,
 }
+
+/// Entities gain energy according to their speed, and can take an action once they have enough
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Energy {
+    pub speed: i32,
+    pub energy: i32,
+}
+
+/// The entity has gained enough energy to act during this tick
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct MyTurn {}
+
+/// Leaves a corpse behind when it dies
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct LeavesCorpse {}
+
+/// Intent of an entity to attack another one in melee this turn
+#[derive(Component)]
+pub struct WantsToMelee {
+    pub target: Entity,
+}
+
+/// Accumulates all the damage an entity receives during a turn, so that it can be applied at once
+#[derive(Component)]
+pub struct SufferDamage {
+    pub amount: Vec<i32>,
+}
+
+impl SufferDamage {
+    /// Add some damage to the entity, creating the component if it didn't have one yet
+    pub fn new_damage(store: &mut WriteStorage<SufferDamage>, victim: Entity, amount: i32) {
+        if let Some(suffering) = store.get_mut(victim) {
+            suffering.amount.push(amount);
+        } else {
+            let dmg = SufferDamage {
+                amount: vec![amount],
+            };
+            store.insert(victim, dmg).expect("Unable to insert damage");
+        }
+    }
+}
+
+/// Entities that can be picked up and carried around
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Item {}
+
+/// The item is in the backpack of its owner instead of lying on the map
+#[derive(Component, ConvertSaveload, Clone)]
+pub struct InBackpack {
+    pub owner: Entity,
+}
+
+/// Intent of an entity to pick up an item from the tile it's standing on
+#[derive(Component)]
+pub struct WantsToPickupItem {
+    pub collected_by: Entity,
+    pub item: Entity,
+}
+
+/// The item is destroyed when used
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Consumable {}
+
+/// Using the item heals its target by some amount, up to its maximum hp
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct ProvidesHealing {
+    pub amount: i32,
+}
+
+/// Using the item damages its target
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct InflictsDamage {
+    pub amount: i32,
+}
+
+/// The item is used on a visible tile within the given range instead of on its user
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Ranged {
+    pub range: i32,
+}
+
+/// Intent of an entity to use an item from its backpack, optionally on some target tile
+#[derive(Component)]
+pub struct WantsToUseItem {
+    pub item: Entity,
+    pub target: Option<Position>,
+}
+
+/// Marker for entities that should be saved to disk when saving the game
+pub struct SerializeMe;
@@ -0,0 +1,93 @@
+use crate::components::*;
+use crate::gamelog::GameLog;
+use crate::map::Map;
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use specs::saveload::{MarkedBuilder, SimpleMarker};
+
+/// System that applies all the damage accumulated during the turn to the fighters
+pub struct DamageSystem {}
+
+impl<'a> System<'a> for DamageSystem {
+    type SystemData = (WriteStorage<'a, Fighter>, WriteStorage<'a, SufferDamage>);
+
+    fn run(&mut self, (mut fighters, mut damage): Self::SystemData) {
+        for (fighter, damage) in (&mut fighters, &damage).join() {
+            fighter.hp -= damage.amount.iter().sum::<i32>();
+        }
+
+        damage.clear();
+    }
+}
+
+/// Remove all the entities that have died this turn, leaving a corpse behind for those that should
+/// have one. The player is never deleted; instead, returns true if the player has died.
+pub fn delete_the_dead(ecs: &mut World) -> bool {
+    let mut player_dead = false;
+    let mut dead: Vec<(Entity, Position, bool)> = Vec::new();
+    let mut corpses: Vec<(Position, Name, Renderable)> = Vec::new();
+    {
+        let entities = ecs.entities();
+        let fighters = ecs.read_storage::<Fighter>();
+        let players = ecs.read_storage::<Player>();
+        let positions = ecs.read_storage::<Position>();
+        let names = ecs.read_storage::<Name>();
+        let renderables = ecs.read_storage::<Renderable>();
+        let leaves_corpse = ecs.read_storage::<LeavesCorpse>();
+        let blockers = ecs.read_storage::<Blocking>();
+        for (ent, fighter, pos) in (&entities, &fighters, &positions).join() {
+            if fighter.hp > 0 {
+                continue;
+            }
+            if players.get(ent).is_some() {
+                player_dead = true;
+                continue;
+            }
+            let name = match names.get(ent) {
+                Some(n) => n.name.clone(),
+                None => String::from("Something"),
+            };
+            ecs.write_resource::<GameLog>()
+                .add(format!("{} dies.", name), RGB::named(ORANGE));
+            dead.push((ent, *pos, blockers.get(ent).is_some()));
+            if leaves_corpse.get(ent).is_some() {
+                let fg = match renderables.get(ent) {
+                    Some(r) => r.fg,
+                    None => RGB::named(RED),
+                };
+                corpses.push((
+                    *pos,
+                    Name {
+                        name: format!("{} corpse", name),
+                    },
+                    Renderable {
+                        glyph: to_cp437('%'),
+                        fg,
+                        bg: RGB::named(BLACK),
+                        render_order: 2,
+                    },
+                ));
+            }
+        }
+    }
+
+    // Delete the dead and take them out of the map right away, so they don't block anything
+    for (victim, pos, blocking) in dead {
+        ecs.delete_entity(victim).expect("Unable to delete entity");
+        ecs.write_resource::<Map>()
+            .remove_entity(victim, pos, blocking);
+    }
+
+    for (pos, name, renderable) in corpses {
+        let corpse = ecs
+            .create_entity()
+            .with(pos)
+            .with(name)
+            .with(renderable)
+            .marked::<SimpleMarker<SerializeMe>>()
+            .build();
+        ecs.write_resource::<Map>().add_entity(corpse, pos, false);
+    }
+
+    player_dead
+}
@@ -0,0 +1,241 @@
+use crate::components::{Position, TileSet, Vision};
+use crate::map::{Map, TileType};
+use crate::tile_registry::{load_tiles, TILES_FILE};
+use crate::visibility_system::set_visible_tiles;
+use bracket_lib::prelude::*;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+const MAP_SIZE: i32 = 400;
+const VIEWERS: usize = 300;
+const VISION_RANGE: i32 = 8;
+const ITERATIONS: u32 = 200;
+/// Each way is timed this many times, keeping the fastest to leave out noise from other processes
+const ROUNDS: u32 = 10;
+/// Percentage of the map covered by pillars, so that the FOV is not trivial
+const PILLAR_PERCENT: i32 = 15;
+
+/// Time taken by all the iterations to update the visible tiles and to check what each entity sees,
+/// and how many entities were seen, which is the same for every way of doing it
+#[derive(Clone, Copy)]
+struct Timings {
+    update: Duration,
+    queries: Duration,
+    seen: usize,
+}
+
+impl Timings {
+    fn total(&self) -> Duration {
+        self.update + self.queries
+    }
+}
+
+/// Keep the fastest of two timings of the same way
+fn fastest(a: Option<Timings>, b: Timings) -> Option<Timings> {
+    match a {
+        Some(a) if a.total() <= b.total() => Some(a),
+        _ => Some(b),
+    }
+}
+
+/// Compare ways of keeping the visible tiles on a large map with many entities that can see. Each
+/// iteration every entity replaces its visible tiles with the ones in its FOV and checks whether it
+/// can see the player, and the player checks which of the entities it can see. The FOV itself is
+/// computed the same way whatever is done with it, so it's computed only once beforehand and timed
+/// apart.
+///
+/// Run it with `cargo test --release fov_benchmark -- --ignored --nocapture`.
+#[test]
+#[ignore]
+fn fov_benchmark() {
+    load_tiles(TILES_FILE).expect("Unable to load the tiles");
+    let mut rng = RandomNumberGenerator::seeded(0);
+    let mut map = Map::new(MAP_SIZE, MAP_SIZE, 1);
+    for y in 1..MAP_SIZE - 1 {
+        for x in 1..MAP_SIZE - 1 {
+            if rng.range(0, 100) >= PILLAR_PERCENT {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
+            }
+        }
+    }
+
+    // The first one is the player
+    let positions: Vec<Position> = (0..=VIEWERS)
+        .map(|_| Position::new(rng.range(1, MAP_SIZE - 1), rng.range(1, MAP_SIZE - 1)))
+        .collect();
+    let start = Instant::now();
+    let fovs: Vec<Vec<Point>> = positions
+        .iter()
+        .map(|pos| field_of_view(Point::new(pos.x, pos.y), VISION_RANGE, &map))
+        .collect();
+    let fov_time = start.elapsed();
+
+    // The rounds of each way are interleaved, so that they all run in the same conditions
+    let (mut list, mut hash_set, mut tile_set) = (None, None, None);
+    for _ in 0..ROUNDS {
+        list = fastest(list, run_position_list(&mut map.clone(), &positions, &fovs));
+        hash_set = fastest(hash_set, run_hash_set(&mut map.clone(), &positions, &fovs));
+        tile_set = fastest(tile_set, run_tile_set(&mut map.clone(), &positions, &fovs));
+    }
+    let (list, hash_set, tile_set) = (list.unwrap(), hash_set.unwrap(), tile_set.unwrap());
+    // The work can't be optimized away if its result is checked
+    assert_eq!(list.seen, hash_set.seen);
+    assert_eq!(list.seen, tile_set.seen);
+
+    println!(
+        "Visible tiles of {} entities on a {}x{} map, per iteration (fastest of {} rounds of {}):",
+        VIEWERS + 1,
+        MAP_SIZE,
+        MAP_SIZE,
+        ROUNDS,
+        ITERATIONS
+    );
+    println!("  computing the FOV:          {:?}", fov_time);
+    for (name, timings) in [
+        ("position list, full clear", list),
+        ("hash set, partial clear", hash_set),
+        ("tile set, partial clear", tile_set),
+    ] {
+        println!(
+            "  {:<27} {:?} ({:?} updating, {:?} checking)",
+            format!("{}:", name),
+            timings.total() / ITERATIONS,
+            timings.update / ITERATIONS,
+            timings.queries / ITERATIONS
+        );
+    }
+}
+
+/// Keep the visible tiles as a list of positions, clearing the whole map when the player's change.
+/// This is how it was done before the tile sets.
+fn run_position_list(map: &mut Map, positions: &[Position], fovs: &[Vec<Point>]) -> Timings {
+    let mut visions: Vec<Vec<Position>> = vec![Vec::new(); positions.len()];
+    let mut timings = Timings {
+        update: Duration::ZERO,
+        queries: Duration::ZERO,
+        seen: 0,
+    };
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        for (i, fov) in fovs.iter().enumerate() {
+            visions[i] = fov
+                .iter()
+                .filter(|p| map.in_bounds(**p))
+                .map(|p| Position::new(p.x, p.y))
+                .collect();
+            if i == 0 {
+                for visible in map.visible.iter_mut() {
+                    *visible = false;
+                }
+                for pos in visions[0].iter() {
+                    let idx = map.xy_idx(pos.x, pos.y);
+                    map.explored[idx] = true;
+                    map.visible[idx] = true;
+                }
+            }
+        }
+        let updated = Instant::now();
+        timings.update += updated - start;
+
+        timings.seen += positions[1..]
+            .iter()
+            .filter(|pos| visions[0].contains(pos))
+            .count();
+        timings.seen += visions[1..]
+            .iter()
+            .filter(|vision| vision.contains(&positions[0]))
+            .count();
+        timings.queries += updated.elapsed();
+    }
+    timings
+}
+
+/// Keep the visible tiles as a hash set of map indexes, clearing only the tiles the player saw
+fn run_hash_set(map: &mut Map, positions: &[Position], fovs: &[Vec<Point>]) -> Timings {
+    let mut visions: Vec<HashSet<usize>> = vec![HashSet::new(); positions.len()];
+    let indexes: Vec<usize> = positions
+        .iter()
+        .map(|pos| map.xy_idx(pos.x, pos.y))
+        .collect();
+    let mut timings = Timings {
+        update: Duration::ZERO,
+        queries: Duration::ZERO,
+        seen: 0,
+    };
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        for (i, fov) in fovs.iter().enumerate() {
+            if i == 0 {
+                for idx in visions[0].iter() {
+                    map.visible[*idx] = false;
+                }
+            }
+            visions[i].clear();
+            for p in fov {
+                if map.in_bounds(*p) {
+                    visions[i].insert(map.point2d_to_index(*p));
+                }
+            }
+            if i == 0 {
+                for idx in visions[0].iter() {
+                    map.explored[*idx] = true;
+                    map.visible[*idx] = true;
+                }
+            }
+        }
+        let updated = Instant::now();
+        timings.update += updated - start;
+
+        timings.seen += indexes[1..]
+            .iter()
+            .filter(|idx| visions[0].contains(idx))
+            .count();
+        timings.seen += visions[1..]
+            .iter()
+            .filter(|vision| vision.contains(&indexes[0]))
+            .count();
+        timings.queries += updated.elapsed();
+    }
+    timings
+}
+
+/// Keep the visible tiles in a tile set, the way the visibility system does
+fn run_tile_set(map: &mut Map, positions: &[Position], fovs: &[Vec<Point>]) -> Timings {
+    let mut visions: Vec<Vision> = positions
+        .iter()
+        .map(|_| Vision {
+            visible: TileSet::default(),
+            range: VISION_RANGE,
+            recompute: true,
+        })
+        .collect();
+    let indexes: Vec<usize> = positions
+        .iter()
+        .map(|pos| map.xy_idx(pos.x, pos.y))
+        .collect();
+    let mut timings = Timings {
+        update: Duration::ZERO,
+        queries: Duration::ZERO,
+        seen: 0,
+    };
+    for _ in 0..ITERATIONS {
+        let start = Instant::now();
+        for (i, fov) in fovs.iter().enumerate() {
+            set_visible_tiles(map, &mut visions[i], fov, i == 0);
+        }
+        let updated = Instant::now();
+        timings.update += updated - start;
+
+        timings.seen += indexes[1..]
+            .iter()
+            .filter(|idx| visions[0].visible.contains(**idx))
+            .count();
+        timings.seen += visions[1..]
+            .iter()
+            .filter(|vision| vision.visible.contains(indexes[0]))
+            .count();
+        timings.queries += updated.elapsed();
+    }
+    timings
+}
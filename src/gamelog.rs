@@ -0,0 +1,34 @@
+use bracket_lib::prelude::*;
+use serde::{Deserialize, Serialize};
+
+/// A message shown to the player, along with the turn in which it happened
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LogEntry {
+    pub turn: u32,
+    pub text: String,
+    pub color: RGB,
+}
+
+/// Resource with all the messages shown to the player during the game. It also keeps the global
+/// turn counter, increased every time the player takes a turn, to stamp the messages with.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct GameLog {
+    pub entries: Vec<LogEntry>,
+    pub turn: u32,
+}
+
+impl GameLog {
+    /// Add a message in the current turn with the given color
+    pub fn add<S: ToString>(&mut self, text: S, color: RGB) {
+        self.entries.push(LogEntry {
+            turn: self.turn,
+            text: text.to_string(),
+            color,
+        });
+    }
+
+    /// Add a message in the current turn with the default color
+    pub fn info<S: ToString>(&mut self, text: S) {
+        self.add(text, RGB::named(WHITE));
+    }
+}
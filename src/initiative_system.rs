@@ -0,0 +1,50 @@
+use crate::components::{Energy, MyTurn, Player};
+use specs::prelude::*;
+
+/// Energy an entity needs to take an action, which is spent when taking it
+pub const ACTION_COST: i32 = 100;
+
+/// Speed of a regular entity, that gets to act once per player turn
+pub const NORMAL_SPEED: i32 = 10;
+
+/// Spend the energy of an entity for taking an action
+pub fn spend_energy(ecs: &mut World, ent: Entity) {
+    if let Some(energy) = ecs.write_storage::<Energy>().get_mut(ent) {
+        energy.energy -= ACTION_COST;
+    }
+}
+
+/// System that grants energy to all entities according to their speed. Entities other than the
+/// player that reach the action cost get their turn. Afterwards, `player_ready` is true if it's the
+/// player's turn.
+pub struct InitiativeSystem {
+    pub player_ready: bool,
+}
+
+impl<'a> System<'a> for InitiativeSystem {
+    type SystemData = (
+        Entities<'a>,
+        WriteStorage<'a, Energy>,
+        WriteStorage<'a, MyTurn>,
+        ReadStorage<'a, Player>,
+    );
+
+    fn run(&mut self, (entities, mut energies, mut turns, players): Self::SystemData) {
+        // Turns from the previous tick have already been taken
+        turns.clear();
+
+        for (ent, energy) in (&entities, &mut energies).join() {
+            energy.energy += energy.speed;
+            if energy.energy < ACTION_COST {
+                continue;
+            }
+            if players.get(ent).is_some() {
+                // The player spends the energy once the action is actually taken
+                self.player_ready = true;
+            } else {
+                energy.energy -= ACTION_COST;
+                turns.insert(ent, MyTurn {}).expect("Unable to insert turn");
+            }
+        }
+    }
+}
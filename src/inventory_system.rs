@@ -0,0 +1,141 @@
+use crate::components::*;
+use crate::gamelog::GameLog;
+use crate::map::Map;
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use std::cmp::min;
+
+/// System that moves the items entities want to pick up from the map to their backpacks
+pub struct ItemCollectionSystem {}
+
+impl<'a> System<'a> for ItemCollectionSystem {
+    type SystemData = (
+        WriteExpect<'a, Map>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Entity>, // Player entity
+        WriteStorage<'a, WantsToPickupItem>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Name>,
+        WriteStorage<'a, InBackpack>,
+    );
+
+    fn run(
+        &mut self,
+        (mut map, mut log, player_ent, mut wants_pickup, mut positions, names, mut backpack): Self::SystemData,
+    ) {
+        for pickup in wants_pickup.join() {
+            // The item no longer has a place in the map
+            if let Some(pos) = positions.remove(pickup.item) {
+                map.remove_entity(pickup.item, pos, false);
+            }
+            backpack
+                .insert(
+                    pickup.item,
+                    InBackpack {
+                        owner: pickup.collected_by,
+                    },
+                )
+                .expect("Unable to insert item in backpack");
+
+            if pickup.collected_by == *player_ent {
+                if let Some(name) = names.get(pickup.item) {
+                    log.info(format!("You pick up the {}.", name.name));
+                }
+            }
+        }
+
+        wants_pickup.clear();
+    }
+}
+
+/// System that applies the effects of the items entities want to use
+pub struct UseItemSystem {}
+
+impl<'a> System<'a> for UseItemSystem {
+    type SystemData = (
+        ReadExpect<'a, Map>,
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Entity>, // Player entity
+        Entities<'a>,
+        WriteStorage<'a, WantsToUseItem>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Consumable>,
+        ReadStorage<'a, ProvidesHealing>,
+        ReadStorage<'a, InflictsDamage>,
+        WriteStorage<'a, Fighter>,
+        WriteStorage<'a, SufferDamage>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            map,
+            mut log,
+            player_ent,
+            entities,
+            mut wants_use,
+            names,
+            consumables,
+            healing,
+            inflicts_damage,
+            mut fighters,
+            mut suffer_damage,
+        ): Self::SystemData,
+    ) {
+        for (user, use_item) in (&entities, &wants_use).join() {
+            let item_name = match names.get(use_item.item) {
+                Some(name) => name.name.clone(),
+                None => String::from("item"),
+            };
+
+            // Items without a target tile are used on the user itself
+            let targets: Vec<Entity> = match use_item.target {
+                None => vec![user],
+                Some(pos) => map.tile_entities[map.xy_idx(pos.x, pos.y)].clone(),
+            };
+
+            if let Some(healing) = healing.get(use_item.item) {
+                for target in targets.iter() {
+                    if let Some(fighter) = fighters.get_mut(*target) {
+                        fighter.hp = min(fighter.max_hp, fighter.hp + healing.amount);
+                        if user == *player_ent {
+                            log.add(
+                                format!(
+                                    "You use the {}, healing {} hp.",
+                                    item_name, healing.amount
+                                ),
+                                RGB::named(GREEN),
+                            );
+                        }
+                    }
+                }
+            }
+
+            if let Some(damage) = inflicts_damage.get(use_item.item) {
+                for target in targets.iter() {
+                    if fighters.get(*target).is_some() {
+                        SufferDamage::new_damage(&mut suffer_damage, *target, damage.amount);
+                        if user == *player_ent {
+                            let target_name = match names.get(*target) {
+                                Some(name) => name.name.clone(),
+                                None => String::from("enemy"),
+                            };
+                            log.info(format!(
+                                "You use {} on {}, inflicting {} hp.",
+                                item_name, target_name, damage.amount
+                            ));
+                        }
+                    }
+                }
+            }
+
+            if consumables.get(use_item.item).is_some() {
+                entities
+                    .delete(use_item.item)
+                    .expect("Unable to delete consumed item");
+            }
+        }
+
+        wants_use.clear();
+    }
+}
@@ -1,49 +1,181 @@
 use bracket_lib::prelude::*;
 use specs::prelude::*;
+use specs::saveload::{SimpleMarker, SimpleMarkerAllocator};
+use std::collections::HashSet;
 
 #[macro_use]
 extern crate specs_derive;
 
 mod components;
+mod damage_system;
+#[cfg(test)]
+mod fov_benchmark;
+mod gamelog;
+mod initiative_system;
+mod inventory_system;
 mod map;
+mod map_builders;
 mod map_management_system;
+mod melee_combat_system;
 mod monster_ai;
 mod player;
+mod raws;
 mod render;
 mod room;
+mod saveload_system;
+mod spawn_table;
+mod spawner;
+mod targeting;
+mod tile_registry;
 mod visibility_system;
 
 use crate::components::*;
+use crate::damage_system::{delete_the_dead, DamageSystem};
+use crate::gamelog::GameLog;
+use crate::initiative_system::{spend_energy, InitiativeSystem};
+use crate::inventory_system::{ItemCollectionSystem, UseItemSystem};
 use crate::map::Map;
-use crate::map_management_system::MapManagementSystem;
+use crate::map_management_system::{check_spatial_index, MapManagementSystem};
+use crate::melee_combat_system::MeleeCombatSystem;
 use crate::monster_ai::MonsterAISystem;
-use crate::player::player_input;
-use crate::render::{draw_entities, draw_map};
+use crate::player::{
+    explore_step, monsters_in_view, player_input, rest_step, travel_step, use_item,
+};
+use crate::render::{
+    draw_ai_overlay, draw_entities, draw_game_over, draw_hud, draw_log_panel, draw_look, draw_map,
+    draw_targeting, draw_tooltip, show_inventory, show_log_history, show_main_menu, ItemMenuResult,
+    MainMenuResult,
+};
+use crate::saveload_system::{delete_save, load_game, save_exists};
+use crate::targeting::{look_input, targeting_input};
 use crate::visibility_system::VisibilitySystem;
 
 const CONSOLE_WIDTH: i32 = 80;
 const CONSOLE_HEIGHT: i32 = 50;
+// The bottom of the screen is reserved for the message log
+const MAP_HEIGHT: i32 = 43;
 const TILE_SIZE: i32 = 16;
 
 // Main game state
 
 #[derive(PartialEq, Copy, Clone)]
+pub enum MainMenuSelection {
+    NewGame,
+    Continue,
+    Quit,
+}
+
+#[derive(PartialEq, Clone)]
 pub enum TurnState {
+    MainMenu {
+        selected: MainMenuSelection,
+    },
     Paused,
     Running,
+    NextLevel,
+    Resting,
+    AutoExploring,
+    Travelling {
+        target: Position,
+        monsters_seen: HashSet<Entity>,
+    },
+    ShowInventory,
+    ShowLog {
+        scroll: usize,
+    },
+    Look {
+        cursor: Position,
+    },
+    ShowTargeting {
+        range: i32,
+        item: Entity,
+        cursor: Position,
+    },
+    GameOver,
 }
 
 pub struct State {
     ecs: World,
+    turn_state: TurnState,
+    seed: Option<u64>, // Seed given by the user, if any
+    ai_overlay: bool,  // Debug overlay with the state of the monster AI
 }
 
 impl State {
-    pub fn run_systems(&mut self) {
-        VisibilitySystem {}.run_now(&self.ecs);
-        MonsterAISystem {}.run_now(&self.ecs);
-        MapManagementSystem {}.run_now(&self.ecs);
-        // Apply now all changes to the ECS that may be queued from running the systems
-        self.ecs.maintain();
+    /// Resolve the action the player has just taken, and then let the rest of entities act as they
+    /// gain energy, until it's the player's turn again. Returns true if the player has died.
+    pub fn run_systems(&mut self) -> bool {
+        let player = *self.ecs.fetch::<Entity>();
+        spend_energy(&mut self.ecs, player);
+
+        loop {
+            VisibilitySystem {}.run_now(&self.ecs);
+            MonsterAISystem {}.run_now(&self.ecs);
+            MeleeCombatSystem {}.run_now(&self.ecs);
+            ItemCollectionSystem {}.run_now(&self.ecs);
+            UseItemSystem {}.run_now(&self.ecs);
+            DamageSystem {}.run_now(&self.ecs);
+            // Apply now all changes to the ECS that may be queued from running the systems
+            self.ecs.maintain();
+            if delete_the_dead(&mut self.ecs) {
+                return true;
+            }
+            if cfg!(debug_assertions) {
+                check_spatial_index(&self.ecs);
+            }
+
+            let mut initiative = InitiativeSystem {
+                player_ready: false,
+            };
+            initiative.run_now(&self.ecs);
+            self.ecs.maintain();
+            if initiative.player_ready {
+                return false;
+            }
+        }
+    }
+
+    /// Go down the stairs: everything but the player and what they carry is deleted, and a new
+    /// deeper level is created
+    pub fn goto_next_level(&mut self) {
+        let to_delete: Vec<Entity> = {
+            let entities = self.ecs.entities();
+            let players = self.ecs.read_storage::<Player>();
+            let backpack = self.ecs.read_storage::<InBackpack>();
+            let player = *self.ecs.fetch::<Entity>();
+            (&entities, !&players)
+                .join()
+                .filter(|(ent, _)| match backpack.get(*ent) {
+                    Some(item) => item.owner != player,
+                    None => true,
+                })
+                .map(|(ent, _)| ent)
+                .collect()
+        };
+        self.ecs
+            .delete_entities(&to_delete)
+            .expect("Unable to delete entities");
+
+        let depth = self.ecs.fetch::<Map>().depth + 1;
+        generate_level(&mut self.ecs, depth);
+        self.ecs
+            .write_resource::<GameLog>()
+            .info(format!("You descend to level {}.", depth));
+    }
+
+    /// Throw away the current world and start over with a new one
+    pub fn new_game(&mut self) {
+        self.ecs = build_world(pick_seed(self.seed));
+    }
+
+    /// Load the saved game. If it can't be loaded, a new game is started instead.
+    pub fn continue_game(&mut self) {
+        if let Err(e) = load_game(&mut self.ecs) {
+            self.new_game();
+            self.ecs
+                .write_resource::<GameLog>()
+                .add(format!("Unable to load the game: {}", e), RGB::named(RED));
+        }
     }
 }
 
@@ -51,22 +183,268 @@ impl GameState for State {
     fn tick(&mut self, ctx: &mut BTerm) {
         ctx.cls();
 
-        let turn_state = player_input(&mut self.ecs, ctx);
-
-        // Run systems
-        if turn_state == TurnState::Running {
-            self.run_systems();
+        // There is no game to draw in the main menu
+        if let TurnState::MainMenu { selected } = self.turn_state {
+            self.turn_state = match show_main_menu(ctx, selected, save_exists()) {
+                MainMenuResult::NoSelection { selected } => TurnState::MainMenu { selected },
+                MainMenuResult::Selected { selected } => match selected {
+                    MainMenuSelection::NewGame => {
+                        self.new_game();
+                        TurnState::Paused
+                    }
+                    MainMenuSelection::Continue => {
+                        self.continue_game();
+                        TurnState::Paused
+                    }
+                    MainMenuSelection::Quit => {
+                        ctx.quit();
+                        self.turn_state.clone()
+                    }
+                },
+            };
+            return;
         }
 
-        // Render stuff
+        // Render stuff. Menus are drawn on top of this while handling their state below.
         draw_map(&self.ecs, ctx);
         draw_entities(&self.ecs, ctx);
+        draw_log_panel(&self.ecs, ctx);
+        draw_hud(&self.ecs, ctx);
+        if ctx.key == Some(VirtualKeyCode::F2) {
+            self.ai_overlay = !self.ai_overlay;
+        }
+        if self.ai_overlay {
+            draw_ai_overlay(&self.ecs, ctx);
+        }
+
+        let mut new_state = self.turn_state.clone();
+        // Multi-turn commands go back to their state after each turn they take
+        let mut repeat = None;
+        match self.turn_state.clone() {
+            TurnState::GameOver => {
+                // The game is frozen until the player chooses to restart or quit
+                draw_game_over(ctx);
+                match ctx.key {
+                    Some(VirtualKeyCode::R) => {
+                        self.new_game();
+                        new_state = TurnState::Paused;
+                    }
+                    Some(VirtualKeyCode::Escape) => ctx.quit(),
+                    _ => {}
+                }
+            }
+            TurnState::ShowInventory => {
+                let (result, item) = show_inventory(&self.ecs, ctx);
+                match result {
+                    ItemMenuResult::Cancel => new_state = TurnState::Paused,
+                    ItemMenuResult::NoResponse => {}
+                    ItemMenuResult::Selected => {
+                        new_state = use_item(&mut self.ecs, item.unwrap(), None);
+                    }
+                }
+            }
+            TurnState::ShowLog { scroll } => match show_log_history(&self.ecs, ctx, scroll) {
+                Some(scroll) => new_state = TurnState::ShowLog { scroll },
+                None => new_state = TurnState::Paused,
+            },
+            TurnState::Resting => {
+                new_state = rest_step(&mut self.ecs, ctx);
+                repeat = Some(TurnState::Resting);
+            }
+            TurnState::AutoExploring => {
+                new_state = explore_step(&mut self.ecs, ctx);
+                repeat = Some(TurnState::AutoExploring);
+            }
+            TurnState::Travelling {
+                target,
+                monsters_seen,
+            } => {
+                // Monsters that went out of sight count as new ones if they come back
+                repeat = Some(TurnState::Travelling {
+                    target,
+                    monsters_seen: monsters_seen
+                        .intersection(&monsters_in_view(&self.ecs))
+                        .copied()
+                        .collect(),
+                });
+                new_state = travel_step(&mut self.ecs, ctx, target, &monsters_seen);
+            }
+            TurnState::Look { cursor } => {
+                draw_look(&self.ecs, ctx, cursor);
+                new_state = look_input(&mut self.ecs, ctx, cursor);
+            }
+            TurnState::ShowTargeting {
+                range,
+                item,
+                cursor,
+            } => {
+                draw_targeting(&self.ecs, ctx, range, cursor);
+                new_state = targeting_input(&mut self.ecs, ctx, range, item, cursor);
+            }
+            _ => {
+                draw_tooltip(&self.ecs, ctx, Position::from(ctx.mouse_pos()));
+                new_state = player_input(&mut self.ecs, ctx);
+            }
+        }
+
+        if new_state == TurnState::NextLevel {
+            self.goto_next_level();
+            new_state = TurnState::Paused;
+        }
+
+        // Run systems
+        if new_state == TurnState::Running {
+            self.ecs.write_resource::<GameLog>().turn += 1;
+            if self.run_systems() {
+                // Death is permanent, so the save file is no longer any good
+                if let Err(e) = delete_save() {
+                    self.ecs.write_resource::<GameLog>().add(
+                        format!("Unable to delete the saved game: {}", e),
+                        RGB::named(RED),
+                    );
+                }
+                new_state = TurnState::GameOver;
+            } else if let Some(state) = repeat {
+                new_state = state;
+            }
+        }
+
+        self.turn_state = new_state;
+    }
+}
+
+/// Return the seed given by the user, or a random one if there is none. The seed is logged so that
+/// the game can be reproduced later.
+fn pick_seed(seed: Option<u64>) -> u64 {
+    let seed = seed.unwrap_or_else(|| RandomNumberGenerator::new().next_u64());
+    console::log(format!("Using seed {}", seed));
+    seed
+}
+
+/// Parse the command line arguments, returning the seed passed with --seed, if any
+fn parse_args() -> Option<u64> {
+    let mut seed = None;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = if arg == "--seed" {
+            args.next()
+        } else if let Some(value) = arg.strip_prefix("--seed=") {
+            Some(value.to_string())
+        } else {
+            eprintln!("Unknown argument: {}", arg);
+            eprintln!("Usage: roguelike-rust-test [--seed <number>]");
+            std::process::exit(1);
+        };
+        match value.as_deref().map(str::parse::<u64>) {
+            Some(Ok(value)) => seed = Some(value),
+            _ => {
+                eprintln!("--seed expects a non-negative integer");
+                std::process::exit(1);
+            }
+        }
+    }
+    seed
+}
+
+/// Create an empty world with all components registered
+fn empty_world() -> World {
+    let mut ecs = World::new();
+    ecs.register::<Position>();
+    ecs.register::<Name>();
+    ecs.register::<Renderable>();
+    ecs.register::<Player>();
+    ecs.register::<Vision>();
+    ecs.register::<Monster>();
+    ecs.register::<Ai>();
+    ecs.register::<Blocking>();
+    ecs.register::<Fighter>();
+    ecs.register::<WantsToMelee>();
+    ecs.register::<SufferDamage>();
+    ecs.register::<LeavesCorpse>();
+    ecs.register::<Energy>();
+    ecs.register::<MyTurn>();
+    ecs.register::<Item>();
+    ecs.register::<InBackpack>();
+    ecs.register::<WantsToPickupItem>();
+    ecs.register::<Consumable>();
+    ecs.register::<ProvidesHealing>();
+    ecs.register::<InflictsDamage>();
+    ecs.register::<Ranged>();
+    ecs.register::<WantsToUseItem>();
+    ecs.register::<SimpleMarker<SerializeMe>>();
+    ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+    ecs
+}
+
+/// Create a new world with all components registered, a fresh map, and the player and monsters
+/// spawned in it. The same seed always produces the same world.
+fn build_world(seed: u64) -> World {
+    let mut ecs = empty_world();
+
+    // Add ECS resources: RNG shared by everything that needs randomness, and the first level
+    ecs.insert(RandomNumberGenerator::seeded(seed));
+    let mut log = GameLog::default();
+    log.add("Welcome to the dungeon!", RGB::named(YELLOW));
+    ecs.insert(log);
+    generate_level(&mut ecs, 1);
+
+    ecs
+}
+
+/// Generate a new map for the given depth as the current one, and populate it with monsters. The
+/// player is placed at the start of the map, and created if it doesn't exist yet.
+fn generate_level(ecs: &mut World, depth: i32) {
+    let seed = ecs.write_resource::<RandomNumberGenerator>().next_u64();
+    let builder = match map_builders::build_level(CONSOLE_WIDTH, MAP_HEIGHT, depth, seed) {
+        Ok(builder) => builder,
+        Err(e) => {
+            // Only broken tile definitions make every map fail
+            eprintln!("Unable to generate a valid map for level {}: {}", depth, e);
+            eprintln!("Check the walkable tiles in {}", tile_registry::TILES_FILE);
+            std::process::exit(1);
+        }
+    };
+    let player_pos = builder.get_starting_position();
+    ecs.insert(builder.get_map());
+    ecs.insert(player_pos);
+
+    // Also store the player entity as a resource so that systems can target it
+    let player = ecs.try_fetch::<Entity>().map(|player| *player);
+    match player {
+        Some(player) => {
+            *ecs.write_storage::<Position>()
+                .get_mut(player)
+                .expect("Player has no position") = player_pos;
+            if let Some(vision) = ecs.write_storage::<Vision>().get_mut(player) {
+                vision.recompute = true;
+            }
+        }
+        None => {
+            let player = spawner::player(ecs, player_pos);
+            ecs.insert(player);
+        }
     }
+
+    spawner::populate_regions(ecs, &builder.get_spawn_regions());
+
+    // Run some systems that need to be run before the first turn
+    VisibilitySystem {}.run_now(ecs);
+    MapManagementSystem {}.run_now(ecs);
 }
 
 embedded_resource!(TILE_FONT, "../res/terminal16x16.png");
 
 fn main() {
+    let seed = parse_args();
+    if let Err(e) = tile_registry::load_tiles(tile_registry::TILES_FILE) {
+        eprintln!("Unable to load {}: {}", tile_registry::TILES_FILE, e);
+        std::process::exit(1);
+    }
+    if let Err(e) = raws::load_raws(raws::RAWS_FILE) {
+        eprintln!("Unable to load {}: {}", raws::RAWS_FILE, e);
+        std::process::exit(1);
+    }
+
     link_resource!(TILE_FONT, "resources/terminal16x16.png");
 
     let context = BTermBuilder::new()
@@ -76,88 +454,93 @@ fn main() {
         .with_font("terminal16x16.png", TILE_SIZE, TILE_SIZE)
         .with_simple_console(CONSOLE_WIDTH, CONSOLE_HEIGHT, "terminal16x16.png")
         .build();
-    let mut gs = State { ecs: World::new() };
-
-    // ECS setup
-    // Register all components
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Vision>();
-    gs.ecs.register::<Monster>();
-    gs.ecs.register::<Blocking>();
-    gs.ecs.register::<Fighter>();
-
-    // Add ECS resources: map, and player position
-    let map = Map::new(CONSOLE_WIDTH, CONSOLE_HEIGHT);
-    let player_pos = Position::from(map.rooms[0].center());
-    gs.ecs.insert(map);
-    gs.ecs.insert(player_pos);
-
-    // Create player entity
-    gs.ecs
-        .create_entity()
-        .with(player_pos)
-        .with(Name {
-            name: String::from("Hero"),
-        })
-        .with(Renderable {
-            glyph: to_cp437('@'),
-            fg: RGB::named(YELLOW),
-            bg: RGB::named(BLACK),
-        })
-        .with(Player {})
-        .with(Vision {
-            visible: Vec::new(),
-            range: 8,
-            recompute: true,
-        })
-        .with(Fighter {
-            max_hp: 30,
-            hp: 30,
-            attack: 5,
-            defense: 2,
-        })
-        .build();
+    let gs = State {
+        ecs: World::new(),
+        turn_state: TurnState::MainMenu {
+            selected: MainMenuSelection::NewGame,
+        },
+        seed,
+        ai_overlay: false,
+    };
+
+    main_loop(context, gs);
+}
 
-    // Create some monsters in rooms other than the first one (where the player spawns)
-    let rooms;
-    {
-        let map = gs.ecs.fetch::<Map>();
-        rooms = map.rooms.clone();
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tiles of the map, and the position, name and hp (if it fights) of every entity
+    pub type Snapshot = (Vec<map::TileType>, Vec<(Position, String, Option<i32>)>);
+
+    pub fn snapshot(ecs: &World) -> Snapshot {
+        let map = ecs.fetch::<Map>();
+        let positions = ecs.read_storage::<Position>();
+        let names = ecs.read_storage::<Name>();
+        let fighters = ecs.read_storage::<Fighter>();
+        let entities = (&positions, &names, fighters.maybe())
+            .join()
+            .map(|(pos, name, fighter)| (*pos, name.name.clone(), fighter.map(|f| f.hp)))
+            .collect();
+        (map.tiles.clone(), entities)
     }
-    for room in rooms.iter().skip(1) {
-        let pos = Position::from(room.center());
-        gs.ecs
-            .create_entity()
-            .with(pos)
-            .with(Name {
-                name: String::from("Orc"),
-            })
-            .with(Renderable {
-                glyph: to_cp437('o'),
-                fg: RGB::named(GREEN),
-                bg: RGB::named(BLACK),
-            })
-            .with(Vision {
-                visible: Vec::new(),
-                range: 8,
-                recompute: true,
-            })
-            .with(Monster {})
-            .with(Blocking {})
-            .with(Fighter {
-                max_hp: 12,
-                hp: 12,
-                attack: 4,
-                defense: 1,
-            })
-            .build();
+
+    pub fn load_resources() {
+        tile_registry::load_tiles(tile_registry::TILES_FILE).expect("Unable to load the tiles");
+        raws::load_raws(raws::RAWS_FILE).expect("Unable to load the raws");
     }
 
-    // Run some systems that need to be run before the first turn
-    VisibilitySystem {}.run_now(&gs.ecs);
+    /// Let the player wait for the given number of turns while the rest of entities act, or until
+    /// the player dies
+    pub fn wait_turns(ecs: World, turns: i32) -> World {
+        let mut state = State {
+            ecs,
+            turn_state: TurnState::Paused,
+            seed: None,
+            ai_overlay: false,
+        };
+        for _ in 0..turns {
+            player::try_wait(&mut state.ecs);
+            if state.run_systems() {
+                break;
+            }
+        }
+        state.ecs
+    }
 
-    main_loop(context, gs);
+    #[test]
+    fn same_seed_builds_same_world() {
+        load_resources();
+        for seed in 0..20 {
+            let (tiles, entities) = snapshot(&build_world(seed));
+            let (other_tiles, other_entities) = snapshot(&build_world(seed));
+            assert!(tiles == other_tiles, "Different maps with seed {}", seed);
+            assert_eq!(
+                entities, other_entities,
+                "Different entities with seed {}",
+                seed
+            );
+            assert!(entities.len() > 1, "Nothing spawned with seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn same_seed_plays_the_same() {
+        load_resources();
+        for seed in 0..5 {
+            let (_, start_entities) = snapshot(&build_world(seed));
+            let (_, entities) = snapshot(&wait_turns(build_world(seed), 50));
+            let (_, other_entities) = snapshot(&wait_turns(build_world(seed), 50));
+            assert_eq!(
+                entities, other_entities,
+                "Different entities after some turns with seed {}",
+                seed
+            );
+            assert!(
+                entities != start_entities,
+                "Nothing moved with seed {}",
+                seed
+            );
+        }
+    }
 }
@@ -1,91 +1,68 @@
+use super::components::Position;
 use super::room::Room;
+use super::tile_registry::tile_def;
 use bracket_lib::prelude::*;
+use serde::{Deserialize, Serialize};
 use specs::prelude::*;
 use std::cmp::{max, min};
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
 pub enum TileType {
     Wall,
     Floor,
+    DownStairs,
+    DoorClosed,
+    DoorOpen,
 }
 
 impl TileType {
     pub fn is_walkable(&self) -> bool {
-        // To complete as more tile types are added
-        // TODO: Move this data to a JSON config file and load from there
-        match self {
-            TileType::Wall => false,
-            TileType::Floor => true,
-        }
+        tile_def(*self).walkable
+    }
+
+    /// Return true if the tile blocks the sight
+    pub fn is_opaque(&self) -> bool {
+        tile_def(*self).opaque
+    }
+
+    /// Return the multiplier of the cost of moving into the tile
+    pub fn cost(&self) -> f32 {
+        tile_def(*self).cost
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Map {
     pub tiles: Vec<TileType>,
+    /// Only map builders based on rooms fill this, so it can be empty (e.g. in caves)
     pub rooms: Vec<Room>,
     pub width: i32,
     pub height: i32,
+    pub depth: i32,
     pub explored: Vec<bool>,
     pub visible: Vec<bool>,
+    // The following are computed from the entities in the map, so they are not saved
+    #[serde(skip)]
     pub blocked: Vec<bool>,
+    #[serde(skip)]
     pub tile_entities: Vec<Vec<Entity>>,
 }
 
 impl Map {
-    pub fn new(width: i32, height: i32) -> Self {
+    /// Create a map for the given depth filled with walls, to be carved by a map builder
+    pub fn new(width: i32, height: i32, depth: i32) -> Self {
         let total_size = (width * height) as usize;
-        let mut map = Self {
+        Self {
             tiles: vec![TileType::Wall; total_size],
             rooms: Vec::new(),
             width,
             height,
+            depth,
             explored: vec![false; total_size],
             visible: vec![false; total_size],
             blocked: vec![false; total_size],
             tile_entities: vec![Vec::new(); total_size],
-        };
-
-        let mut rooms: Vec<Room> = Vec::new();
-        const MAX_ROOMS: i32 = 12;
-        const MIN_SIZE: i32 = 6;
-        const MAX_SIZE: i32 = 12;
-
-        let mut rng = RandomNumberGenerator::new();
-
-        for _ in 0..MAX_ROOMS {
-            let width = rng.range(MIN_SIZE, MAX_SIZE);
-            let height = rng.range(MIN_SIZE, MAX_SIZE);
-            let x = rng.range(1, map.width - width - 2);
-            let y = rng.range(1, map.height - height - 2);
-            let room = Room::new(x, y, width, height);
-            let mut valid = true;
-            for other_room in rooms.iter() {
-                if room.intersects(other_room) {
-                    valid = false;
-                    break;
-                }
-            }
-            if valid {
-                map.carve_room(&room);
-                // Connect to the previous room
-                if !rooms.is_empty() {
-                    let (new_x, new_y) = room.center();
-                    let (prev_x, prev_y) = rooms.last().unwrap().center();
-                    if rng.range(0, 1) == 1 {
-                        map.carve_corridor_horizontal(prev_x, new_x, prev_y);
-                        map.carve_corridor_vertical(prev_y, new_y, new_x);
-                    } else {
-                        map.carve_corridor_vertical(prev_y, new_y, prev_x);
-                        map.carve_corridor_horizontal(prev_x, new_x, new_y);
-                    }
-                }
-
-                rooms.push(room);
-            }
         }
-
-        map.rooms = rooms;
-        map
     }
 
     pub fn carve_room(&mut self, room: &Room) {
@@ -120,12 +97,63 @@ impl Map {
         }
     }
 
+    /// Allocate again the data that is not saved along with the map. It still has to be computed
+    /// from the entities, e.g. by running the MapManagementSystem.
+    pub fn reset_entity_data(&mut self) {
+        let total_size = (self.width * self.height) as usize;
+        self.blocked = vec![false; total_size];
+        self.tile_entities = vec![Vec::new(); total_size];
+    }
+
     pub fn clear_entities(&mut self) {
         for entities in self.tile_entities.iter_mut() {
             entities.clear();
         }
     }
 
+    /// Put an entity on the tile at the given position
+    pub fn add_entity(&mut self, ent: Entity, pos: Position, blocking: bool) {
+        let idx = self.xy_idx(pos.x, pos.y);
+        self.tile_entities[idx].push(ent);
+        if blocking {
+            self.blocked[idx] = true;
+        }
+    }
+
+    /// Take an entity out of the tile at the given position. There can only be one blocking entity
+    /// in a tile, so if it was blocking the tile is not blocked anymore.
+    pub fn remove_entity(&mut self, ent: Entity, pos: Position, blocking: bool) {
+        let idx = self.xy_idx(pos.x, pos.y);
+        self.tile_entities[idx].retain(|other| *other != ent);
+        if blocking {
+            self.blocked[idx] = !self.tiles[idx].is_walkable();
+        }
+    }
+
+    /// Move an entity between tiles. It has to be called whenever an entity moves, so that the next
+    /// entities to move see where it is.
+    pub fn move_entity(&mut self, ent: Entity, from: Position, to: Position, blocking: bool) {
+        self.remove_entity(ent, from, blocking);
+        self.add_entity(ent, to, blocking);
+    }
+
+    /// Change the type of a tile, keeping it blocked if there is a blocking entity in it
+    pub fn set_tile(&mut self, idx: usize, tile: TileType) {
+        let blocked_by_entity = self.blocked[idx] && self.tiles[idx].is_walkable();
+        self.tiles[idx] = tile;
+        self.blocked[idx] = blocked_by_entity || !tile.is_walkable();
+    }
+
+    /// Open the door at the given index, returning false if there is no closed door there
+    pub fn try_open_door(&mut self, idx: usize) -> bool {
+        if self.tiles[idx] == TileType::DoorClosed {
+            self.set_tile(idx, TileType::DoorOpen);
+            true
+        } else {
+            false
+        }
+    }
+
     pub fn xy_idx(&self, x: i32, y: i32) -> usize {
         ((y * self.width) + x) as usize
     }
@@ -146,7 +174,7 @@ impl Algorithm2D for Map {
 
 impl BaseMap for Map {
     fn is_opaque(&self, idx: usize) -> bool {
-        self.tiles[idx] == TileType::Wall
+        self.tiles[idx].is_opaque()
     }
 
     // From a position given by idx, return the positions an entity can move to, and the cost to move
@@ -166,22 +194,23 @@ impl BaseMap for Map {
             Point { x: -1, y: 1 },
             Point { x: 1, y: -1 },
         ];
-        // Orthogonal movement costs 1
+        // Orthogonal movement costs 1 times the cost of the tile
         for dir in orthogonal.iter() {
             let new_pos = origin + *dir;
             let new_pos_idx = self.point2d_to_index(new_pos);
             if self.in_bounds(new_pos) && !self.blocked[new_pos_idx] {
-                // For now, all tiles have cost 1
-                exits.push((new_pos_idx, 1.0));
+                exits.push((new_pos_idx, self.tiles[new_pos_idx].cost()));
             }
         }
-        // Diagonal movement costs ~sqrt(2)
+        // Diagonal movement costs ~sqrt(2) times the cost of the tile
         for dir in diagonal.iter() {
             let new_pos = origin + *dir;
             let new_pos_idx = self.point2d_to_index(new_pos);
             if self.in_bounds(new_pos) && !self.blocked[new_pos_idx] {
-                // For now, all tiles have cost 1
-                exits.push((new_pos_idx, 1.4142));
+                exits.push((
+                    new_pos_idx,
+                    std::f32::consts::SQRT_2 * self.tiles[new_pos_idx].cost(),
+                ));
             }
         }
         exits
@@ -0,0 +1,136 @@
+use super::common::{add_doors, room_spawn_regions};
+use super::MapBuilder;
+use crate::components::Position;
+use crate::map::{Map, TileType};
+use crate::room::Room;
+use bracket_lib::prelude::*;
+use std::cmp::{max, min};
+
+const MAX_ATTEMPTS: i32 = 240;
+const MIN_SIZE: i32 = 4;
+const MAX_SIZE: i32 = 10;
+
+/// Rooms placed by binary space partitioning: the map is recursively split in quarters and rooms
+/// are placed inside the pieces, so they never overlap. Rooms are connected from left to right.
+pub struct BspMapBuilder {
+    map: Map,
+    rng: RandomNumberGenerator,
+    rects: Vec<Room>,
+}
+
+impl BspMapBuilder {
+    pub fn new(width: i32, height: i32, depth: i32, seed: u64) -> Self {
+        Self {
+            map: Map::new(width, height, depth),
+            rng: RandomNumberGenerator::seeded(seed),
+            rects: Vec::new(),
+        }
+    }
+
+    /// Split a rectangle in quarters that can be used to place more rooms
+    fn add_subrects(&mut self, rect: Room) {
+        let half_width = max((rect.x1 - rect.x0) / 2, 1);
+        let half_height = max((rect.y1 - rect.y0) / 2, 1);
+        self.rects
+            .push(Room::new(rect.x0, rect.y0, half_width, half_height));
+        self.rects.push(Room::new(
+            rect.x0,
+            rect.y0 + half_height,
+            half_width,
+            half_height,
+        ));
+        self.rects.push(Room::new(
+            rect.x0 + half_width,
+            rect.y0,
+            half_width,
+            half_height,
+        ));
+        self.rects.push(Room::new(
+            rect.x0 + half_width,
+            rect.y0 + half_height,
+            half_width,
+            half_height,
+        ));
+    }
+
+    /// Return a room of random size placed at a random spot of the rectangle
+    fn random_sub_rect(&mut self, rect: Room) -> Room {
+        let rect_width = rect.x1 - rect.x0;
+        let rect_height = rect.y1 - rect.y0;
+        let width = max(MIN_SIZE, self.rng.range(1, min(rect_width, MAX_SIZE) + 1));
+        let height = max(MIN_SIZE, self.rng.range(1, min(rect_height, MAX_SIZE) + 1));
+        let x = rect.x0 + self.rng.range(0, max(1, rect_width - width));
+        let y = rect.y0 + self.rng.range(0, max(1, rect_height - height));
+        Room::new(x, y, width, height)
+    }
+
+    /// Return true if the room fits inside the map and is surrounded by solid walls
+    fn is_possible(&self, room: Room) -> bool {
+        for y in room.y0 - 2..=room.y1 + 2 {
+            for x in room.x0 - 2..=room.x1 + 2 {
+                if x < 1 || x > self.map.width - 2 || y < 1 || y > self.map.height - 2 {
+                    return false;
+                }
+                if self.map.tiles[self.map.xy_idx(x, y)] != TileType::Wall {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+impl MapBuilder for BspMapBuilder {
+    fn build(&mut self) {
+        let mut rooms: Vec<Room> = Vec::new();
+
+        self.rects.clear();
+        let first = Room::new(2, 2, self.map.width - 5, self.map.height - 5);
+        self.rects.push(first);
+        self.add_subrects(first);
+
+        for _ in 0..MAX_ATTEMPTS {
+            let rect = self.rects[self.rng.range(0, self.rects.len())];
+            let candidate = self.random_sub_rect(rect);
+            if self.is_possible(candidate) {
+                self.map.carve_room(&candidate);
+                rooms.push(candidate);
+                self.add_subrects(rect);
+            }
+        }
+
+        // Connect the rooms from left to right
+        rooms.sort_by_key(|room| room.x0);
+        for pair in rooms.windows(2) {
+            let (prev_x, prev_y) = pair[0].center();
+            let (new_x, new_y) = pair[1].center();
+            if self.rng.range(0, 2) == 1 {
+                self.map.carve_corridor_horizontal(prev_x, new_x, prev_y);
+                self.map.carve_corridor_vertical(prev_y, new_y, new_x);
+            } else {
+                self.map.carve_corridor_vertical(prev_y, new_y, prev_x);
+                self.map.carve_corridor_horizontal(prev_x, new_x, new_y);
+            }
+        }
+
+        // Place the stairs down in the rightmost room
+        let (stairs_x, stairs_y) = rooms.last().unwrap().center();
+        let stairs_idx = self.map.xy_idx(stairs_x, stairs_y);
+        self.map.tiles[stairs_idx] = TileType::DownStairs;
+
+        self.map.rooms = rooms;
+        add_doors(&mut self.map);
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        Position::from(self.map.rooms[0].center())
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<Position>> {
+        room_spawn_regions(&self.map.rooms)
+    }
+}
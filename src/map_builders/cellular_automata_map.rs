@@ -0,0 +1,139 @@
+use super::common::{grid_spawn_regions, remove_unreachable_areas};
+use super::MapBuilder;
+use crate::components::Position;
+use crate::map::{Map, TileType};
+use bracket_lib::prelude::*;
+
+/// Percentage of the map that starts as floor before smoothing
+const FLOOR_PERCENT: i32 = 55;
+const SMOOTHING_PASSES: i32 = 15;
+/// Caves where less than this percentage of the map can be reached are discarded
+const MIN_REACHABLE_PERCENT: usize = 30;
+/// How many caves are generated at most looking for one that is big enough
+const MAX_ATTEMPTS: i32 = 20;
+
+/// Organic caves, generated from random noise smoothed by a cellular automaton
+pub struct CellularAutomataMapBuilder {
+    map: Map,
+    rng: RandomNumberGenerator,
+    starting_position: Position,
+}
+
+impl CellularAutomataMapBuilder {
+    pub fn new(width: i32, height: i32, depth: i32, seed: u64) -> Self {
+        Self {
+            map: Map::new(width, height, depth),
+            rng: RandomNumberGenerator::seeded(seed),
+            starting_position: Position::new(width / 2, height / 2),
+        }
+    }
+
+    /// Run a pass of the automaton: tiles surrounded by many walls (or by none) become walls, and
+    /// the rest become floor
+    fn smooth(&mut self) {
+        let mut new_tiles = self.map.tiles.clone();
+        for y in 1..self.map.height - 1 {
+            for x in 1..self.map.width - 1 {
+                let mut neighbor_walls = 0;
+                for dy in -1..=1 {
+                    for dx in -1..=1 {
+                        let idx = self.map.xy_idx(x + dx, y + dy);
+                        if (dx != 0 || dy != 0) && self.map.tiles[idx] == TileType::Wall {
+                            neighbor_walls += 1;
+                        }
+                    }
+                }
+                new_tiles[self.map.xy_idx(x, y)] = if neighbor_walls > 4 || neighbor_walls == 0 {
+                    TileType::Wall
+                } else {
+                    TileType::Floor
+                };
+            }
+        }
+        self.map.tiles = new_tiles;
+    }
+
+    /// Generate a cave and return the index of the stairs, or None if no cave was left after
+    /// smoothing. The cave may still be too small if the start ended up in a small pocket, since
+    /// everything unreachable from it is removed.
+    fn try_build(&mut self) -> Option<usize> {
+        let (width, height, depth) = (self.map.width, self.map.height, self.map.depth);
+        self.map = Map::new(width, height, depth);
+
+        // Fill the map with noise, leaving the border as walls
+        for y in 1..self.map.height - 1 {
+            for x in 1..self.map.width - 1 {
+                if self.rng.range(0, 100) < FLOOR_PERCENT {
+                    let idx = self.map.xy_idx(x, y);
+                    self.map.tiles[idx] = TileType::Floor;
+                }
+            }
+        }
+
+        for _ in 0..SMOOTHING_PASSES {
+            self.smooth();
+        }
+
+        // Start at the floor tile closest to the center of the map
+        let center = Point::new(self.map.width / 2, self.map.height / 2);
+        let start_idx = (0..self.map.tiles.len())
+            .filter(|idx| self.map.tiles[*idx] == TileType::Floor)
+            .min_by_key(|idx| {
+                let (x, y) = self.map.idx_xy(*idx);
+                (DistanceAlg::PythagorasSquared.distance2d(center, Point::new(x, y))) as i32
+            })?;
+        self.starting_position = Position::from(self.map.idx_xy(start_idx));
+
+        Some(remove_unreachable_areas(
+            &mut self.map,
+            self.starting_position,
+        ))
+    }
+}
+
+impl MapBuilder for CellularAutomataMapBuilder {
+    fn build(&mut self) {
+        let min_floor = self.map.tiles.len() * MIN_REACHABLE_PERCENT / 100;
+        // If no cave is big enough, keep the biggest one. If there was no cave at all, the map fails
+        // validation and the level is built again with another seed.
+        let mut best: Option<(usize, Map, Position)> = None;
+        for _ in 0..MAX_ATTEMPTS {
+            let stairs_idx = match self.try_build() {
+                Some(stairs_idx) => stairs_idx,
+                None => continue,
+            };
+            self.map.tiles[stairs_idx] = TileType::DownStairs;
+            let floor = self
+                .map
+                .tiles
+                .iter()
+                .filter(|tile| tile.is_walkable())
+                .count();
+            if floor >= min_floor {
+                return;
+            }
+            if best
+                .as_ref()
+                .is_none_or(|(best_floor, _, _)| floor > *best_floor)
+            {
+                best = Some((floor, self.map.clone(), self.starting_position));
+            }
+        }
+        if let Some((_, map, starting_position)) = best {
+            self.map = map;
+            self.starting_position = starting_position;
+        }
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<Position>> {
+        grid_spawn_regions(&self.map, self.starting_position)
+    }
+}
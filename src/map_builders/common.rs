@@ -0,0 +1,113 @@
+use crate::components::Position;
+use crate::map::{Map, TileType};
+use crate::room::Room;
+use bracket_lib::prelude::*;
+
+/// Size of the regions used to group the spawn points of maps without rooms
+const REGION_WIDTH: i32 = 20;
+const REGION_HEIGHT: i32 = 14;
+/// Regions with fewer floor tiles than this are too cramped to spawn anything
+const MIN_REGION_TILES: usize = 8;
+
+/// Turn into walls the floor tiles that can't be reached from the start, and return the index of
+/// the reachable tile that is farthest away from it
+pub fn remove_unreachable_areas(map: &mut Map, start: Position) -> usize {
+    map.compute_blocked();
+    let start_idx = map.xy_idx(start.x, start.y);
+    // No path can be longer than visiting every tile diagonally
+    let max_depth = map.tiles.len() as f32 * std::f32::consts::SQRT_2;
+    let dijkstra_map = DijkstraMap::new(map.width, map.height, &[start_idx], &*map, max_depth);
+
+    let mut farthest_idx = start_idx;
+    let mut farthest_distance = 0.0;
+    for (idx, distance) in dijkstra_map.map.iter().enumerate() {
+        if map.tiles[idx] == TileType::Wall {
+            continue;
+        }
+        if *distance == f32::MAX {
+            map.tiles[idx] = TileType::Wall;
+        } else if *distance > farthest_distance {
+            farthest_idx = idx;
+            farthest_distance = *distance;
+        }
+    }
+    map.compute_blocked();
+    farthest_idx
+}
+
+/// Place closed doors where corridors meet rooms, which are the floor tiles right outside a room
+/// that have walls on both sides
+pub fn add_doors(map: &mut Map) {
+    let is_wall = |map: &Map, x: i32, y: i32| map.tiles[map.xy_idx(x, y)] == TileType::Wall;
+    let mut doors = Vec::new();
+    for room in map.rooms.iter() {
+        for x in room.x0..=room.x1 {
+            for y in [room.y0 - 1, room.y1 + 1] {
+                if !is_wall(map, x, y) && is_wall(map, x - 1, y) && is_wall(map, x + 1, y) {
+                    doors.push(map.xy_idx(x, y));
+                }
+            }
+        }
+        for y in room.y0..=room.y1 {
+            for x in [room.x0 - 1, room.x1 + 1] {
+                if !is_wall(map, x, y) && is_wall(map, x, y - 1) && is_wall(map, x, y + 1) {
+                    doors.push(map.xy_idx(x, y));
+                }
+            }
+        }
+    }
+    for idx in doors {
+        if map.tiles[idx] == TileType::Floor {
+            map.tiles[idx] = TileType::DoorClosed;
+        }
+    }
+}
+
+/// Return the spawn regions of a map with rooms, which are the floor tiles of every room except the
+/// first one, where the player starts
+pub fn room_spawn_regions(rooms: &[Room]) -> Vec<Vec<Position>> {
+    rooms
+        .iter()
+        .skip(1)
+        .map(|room| {
+            let mut region = Vec::new();
+            for y in room.y0..=room.y1 {
+                for x in room.x0..=room.x1 {
+                    region.push(Position::new(x, y));
+                }
+            }
+            region
+        })
+        .collect()
+}
+
+/// Return the spawn regions of a map without rooms, by splitting it in a grid and grouping the floor
+/// tiles of each cell. The cell where the player starts is skipped.
+pub fn grid_spawn_regions(map: &Map, start: Position) -> Vec<Vec<Position>> {
+    let mut regions = Vec::new();
+    for cell_y in (0..map.height).step_by(REGION_HEIGHT as usize) {
+        for cell_x in (0..map.width).step_by(REGION_WIDTH as usize) {
+            let contains = |pos: Position| {
+                pos.x >= cell_x
+                    && pos.x < cell_x + REGION_WIDTH
+                    && pos.y >= cell_y
+                    && pos.y < cell_y + REGION_HEIGHT
+            };
+            if contains(start) {
+                continue;
+            }
+            let region: Vec<Position> = map
+                .tiles
+                .iter()
+                .enumerate()
+                .filter(|(_idx, tile)| **tile == TileType::Floor)
+                .map(|(idx, _tile)| Position::from(map.idx_xy(idx)))
+                .filter(|pos| contains(*pos))
+                .collect();
+            if region.len() >= MIN_REGION_TILES {
+                regions.push(region);
+            }
+        }
+    }
+    regions
+}
@@ -0,0 +1,83 @@
+use super::common::{grid_spawn_regions, remove_unreachable_areas};
+use super::MapBuilder;
+use crate::components::Position;
+use crate::map::{Map, TileType};
+use bracket_lib::prelude::*;
+
+/// Percentage of the map that has to be dug before the map is finished
+const FLOOR_PERCENT: usize = 45;
+/// Number of steps each digger takes before passing out
+const DIGGER_LIFETIME: i32 = 400;
+
+/// Caves dug by diggers walking at random, each one starting from a random spot already dug
+pub struct DrunkardMapBuilder {
+    map: Map,
+    rng: RandomNumberGenerator,
+    starting_position: Position,
+}
+
+impl DrunkardMapBuilder {
+    pub fn new(width: i32, height: i32, depth: i32, seed: u64) -> Self {
+        Self {
+            map: Map::new(width, height, depth),
+            rng: RandomNumberGenerator::seeded(seed),
+            starting_position: Position::new(width / 2, height / 2),
+        }
+    }
+
+    fn floor_tiles(&self) -> Vec<usize> {
+        (0..self.map.tiles.len())
+            .filter(|idx| self.map.tiles[*idx] == TileType::Floor)
+            .collect()
+    }
+}
+
+impl MapBuilder for DrunkardMapBuilder {
+    fn build(&mut self) {
+        let start_idx = self
+            .map
+            .xy_idx(self.starting_position.x, self.starting_position.y);
+        self.map.tiles[start_idx] = TileType::Floor;
+
+        let desired_floor = self.map.tiles.len() * FLOOR_PERCENT / 100;
+        let mut floor = self.floor_tiles();
+        while floor.len() < desired_floor {
+            let mut digger = Position::from(self.map.idx_xy(floor[self.rng.range(0, floor.len())]));
+            for _ in 0..DIGGER_LIFETIME {
+                let idx = self.map.xy_idx(digger.x, digger.y);
+                self.map.tiles[idx] = TileType::Floor;
+                // Stumble in a random direction, without digging through the border
+                let step = match self.rng.range(0, 4) {
+                    0 => Position::new(-1, 0),
+                    1 => Position::new(1, 0),
+                    2 => Position::new(0, -1),
+                    _ => Position::new(0, 1),
+                };
+                let next = digger + step;
+                if next.x > 0
+                    && next.x < self.map.width - 1
+                    && next.y > 0
+                    && next.y < self.map.height - 1
+                {
+                    digger = next;
+                }
+            }
+            floor = self.floor_tiles();
+        }
+
+        let stairs_idx = remove_unreachable_areas(&mut self.map, self.starting_position);
+        self.map.tiles[stairs_idx] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        self.starting_position
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<Position>> {
+        grid_spawn_regions(&self.map, self.starting_position)
+    }
+}
@@ -0,0 +1,84 @@
+use super::common::{grid_spawn_regions, remove_unreachable_areas};
+use super::MapBuilder;
+use crate::components::Position;
+use crate::map::{Map, TileType};
+use bracket_lib::prelude::*;
+
+/// A perfect maze generated with a randomized depth-first search. Cells are the tiles at odd
+/// coordinates, and the walls between them are carved as the search visits them.
+pub struct MazeMapBuilder {
+    map: Map,
+    rng: RandomNumberGenerator,
+}
+
+impl MazeMapBuilder {
+    pub fn new(width: i32, height: i32, depth: i32, seed: u64) -> Self {
+        Self {
+            map: Map::new(width, height, depth),
+            rng: RandomNumberGenerator::seeded(seed),
+        }
+    }
+
+    fn is_cell(&self, pos: Position) -> bool {
+        pos.x > 0
+            && pos.x < self.map.width - 1
+            && pos.y > 0
+            && pos.y < self.map.height - 1
+            && pos.x % 2 == 1
+            && pos.y % 2 == 1
+    }
+
+    fn carve(&mut self, pos: Position) {
+        let idx = self.map.xy_idx(pos.x, pos.y);
+        self.map.tiles[idx] = TileType::Floor;
+    }
+}
+
+impl MapBuilder for MazeMapBuilder {
+    fn build(&mut self) {
+        let directions = [
+            Position::new(2, 0),
+            Position::new(-2, 0),
+            Position::new(0, 2),
+            Position::new(0, -2),
+        ];
+
+        let start = self.get_starting_position();
+        self.carve(start);
+        let mut stack = vec![start];
+        while let Some(cell) = stack.last().copied() {
+            let unvisited: Vec<Position> = directions
+                .iter()
+                .map(|dir| cell + *dir)
+                .filter(|next| {
+                    self.is_cell(*next)
+                        && self.map.tiles[self.map.xy_idx(next.x, next.y)] == TileType::Wall
+                })
+                .collect();
+            if unvisited.is_empty() {
+                stack.pop();
+            } else {
+                let next = unvisited[self.rng.range(0, unvisited.len())];
+                // Knock down the wall between both cells
+                self.carve(Position::new((cell.x + next.x) / 2, (cell.y + next.y) / 2));
+                self.carve(next);
+                stack.push(next);
+            }
+        }
+
+        let stairs_idx = remove_unreachable_areas(&mut self.map, start);
+        self.map.tiles[stairs_idx] = TileType::DownStairs;
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        Position::new(1, 1)
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<Position>> {
+        grid_spawn_regions(&self.map, self.get_starting_position())
+    }
+}
@@ -0,0 +1,85 @@
+mod bsp_map;
+mod cellular_automata_map;
+mod common;
+mod drunkard_map;
+mod maze_map;
+mod simple_map;
+mod validation;
+
+use crate::components::Position;
+use crate::map::Map;
+use bracket_lib::prelude::*;
+use bsp_map::BspMapBuilder;
+use cellular_automata_map::CellularAutomataMapBuilder;
+use drunkard_map::DrunkardMapBuilder;
+use maze_map::MazeMapBuilder;
+use simple_map::SimpleMapBuilder;
+pub use validation::{validate_map, MapError};
+
+/// A map generation algorithm. The map is generated by `build`, and the rest of the methods can be
+/// used afterwards to retrieve the results.
+pub trait MapBuilder {
+    /// Generate the map
+    fn build(&mut self);
+    /// Return the generated map
+    fn get_map(&self) -> Map;
+    /// Return where the player should start on the generated map
+    fn get_starting_position(&self) -> Position;
+    /// Return the spawn points of the map grouped by region (e.g. one group per room). The region
+    /// where the player starts is not included.
+    fn get_spawn_regions(&self) -> Vec<Vec<Position>>;
+}
+
+/// How many maps are generated at most looking for one that passes validation
+const MAX_ATTEMPTS: i32 = 100;
+
+/// Build a level of the given depth, generating it again with another seed until it passes
+/// validation. It will always generate the same map for the same seed. If none of the attempts
+/// passes, the error of the last one is returned.
+pub fn build_level(
+    width: i32,
+    height: i32,
+    depth: i32,
+    seed: u64,
+) -> Result<Box<dyn MapBuilder>, MapError> {
+    let mut attempt_seed = seed;
+    let mut attempts = 0;
+    loop {
+        let mut builder = random_builder(width, height, depth, attempt_seed);
+        builder.build();
+        match validate_map(&builder.get_map(), builder.get_starting_position()) {
+            Ok(()) => return Ok(builder),
+            Err(e) => {
+                attempts += 1;
+                if attempts >= MAX_ATTEMPTS {
+                    return Err(e);
+                }
+                console::log(format!("Discarding map of seed {}: {}", attempt_seed, e));
+                attempt_seed = RandomNumberGenerator::seeded(attempt_seed).next_u64();
+            }
+        }
+    }
+}
+
+/// Return the builder for a level of the given depth, that will always generate the same map for
+/// the same seed
+pub fn random_builder(width: i32, height: i32, depth: i32, seed: u64) -> Box<dyn MapBuilder> {
+    let mut rng = RandomNumberGenerator::seeded(seed);
+    let builder_seed = rng.next_u64();
+    // The first level always has the classic rooms and corridors
+    if depth == 1 {
+        return Box::new(SimpleMapBuilder::new(width, height, depth, builder_seed));
+    }
+    match rng.range(0, 5) {
+        0 => Box::new(SimpleMapBuilder::new(width, height, depth, builder_seed)),
+        1 => Box::new(BspMapBuilder::new(width, height, depth, builder_seed)),
+        2 => Box::new(CellularAutomataMapBuilder::new(
+            width,
+            height,
+            depth,
+            builder_seed,
+        )),
+        3 => Box::new(DrunkardMapBuilder::new(width, height, depth, builder_seed)),
+        _ => Box::new(MazeMapBuilder::new(width, height, depth, builder_seed)),
+    }
+}
@@ -0,0 +1,85 @@
+use super::common::{add_doors, room_spawn_regions};
+use super::MapBuilder;
+use crate::components::Position;
+use crate::map::{Map, TileType};
+use crate::room::Room;
+use bracket_lib::prelude::*;
+
+const MAX_ROOMS: i32 = 12;
+const MIN_SIZE: i32 = 6;
+const MAX_SIZE: i32 = 12;
+
+/// Rectangular rooms placed at random, each one connected to the previous one by a corridor
+pub struct SimpleMapBuilder {
+    map: Map,
+    rng: RandomNumberGenerator,
+}
+
+impl SimpleMapBuilder {
+    pub fn new(width: i32, height: i32, depth: i32, seed: u64) -> Self {
+        Self {
+            map: Map::new(width, height, depth),
+            rng: RandomNumberGenerator::seeded(seed),
+        }
+    }
+}
+
+impl MapBuilder for SimpleMapBuilder {
+    fn build(&mut self) {
+        let map = &mut self.map;
+        let rng = &mut self.rng;
+        let mut rooms: Vec<Room> = Vec::new();
+
+        for _ in 0..MAX_ROOMS {
+            let width = rng.range(MIN_SIZE, MAX_SIZE);
+            let height = rng.range(MIN_SIZE, MAX_SIZE);
+            let x = rng.range(1, map.width - width - 2);
+            let y = rng.range(1, map.height - height - 2);
+            let room = Room::new(x, y, width, height);
+            let mut valid = true;
+            for other_room in rooms.iter() {
+                if room.intersects(other_room) {
+                    valid = false;
+                    break;
+                }
+            }
+            if valid {
+                map.carve_room(&room);
+                // Connect to the previous room
+                if !rooms.is_empty() {
+                    let (new_x, new_y) = room.center();
+                    let (prev_x, prev_y) = rooms.last().unwrap().center();
+                    if rng.range(0, 2) == 1 {
+                        map.carve_corridor_horizontal(prev_x, new_x, prev_y);
+                        map.carve_corridor_vertical(prev_y, new_y, new_x);
+                    } else {
+                        map.carve_corridor_vertical(prev_y, new_y, prev_x);
+                        map.carve_corridor_horizontal(prev_x, new_x, new_y);
+                    }
+                }
+
+                rooms.push(room);
+            }
+        }
+
+        // Place the stairs down in the last room
+        let (stairs_x, stairs_y) = rooms.last().unwrap().center();
+        let stairs_idx = map.xy_idx(stairs_x, stairs_y);
+        map.tiles[stairs_idx] = TileType::DownStairs;
+
+        map.rooms = rooms;
+        add_doors(map);
+    }
+
+    fn get_map(&self) -> Map {
+        self.map.clone()
+    }
+
+    fn get_starting_position(&self) -> Position {
+        Position::from(self.map.rooms[0].center())
+    }
+
+    fn get_spawn_regions(&self) -> Vec<Vec<Position>> {
+        room_spawn_regions(&self.map.rooms)
+    }
+}
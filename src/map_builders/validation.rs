@@ -0,0 +1,131 @@
+use crate::components::Position;
+use crate::map::{Map, TileType};
+use std::collections::VecDeque;
+use std::fmt;
+
+#[derive(Debug, PartialEq)]
+pub enum MapError {
+    /// The start is not on a walkable tile
+    BlockedStart(Position),
+    /// A tile on the border of the map is not a wall, so entities could walk out of the map
+    OpenBorder(Position),
+    /// The room with the given index can't be reached from the start
+    UnreachableRoom(usize),
+    NoStairs,
+    UnreachableStairs(Position),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MapError::BlockedStart(pos) => {
+                write!(f, "the start at ({}, {}) is not walkable", pos.x, pos.y)
+            }
+            MapError::OpenBorder(pos) => {
+                write!(f, "the border at ({}, {}) is not a wall", pos.x, pos.y)
+            }
+            MapError::UnreachableRoom(room) => write!(f, "room {} can't be reached", room),
+            MapError::NoStairs => write!(f, "there are no stairs down"),
+            MapError::UnreachableStairs(pos) => {
+                write!(f, "the stairs at ({}, {}) can't be reached", pos.x, pos.y)
+            }
+        }
+    }
+}
+
+/// Check that a generated map is playable: its border is solid, and every room and the stairs
+/// down can be reached from the start
+pub fn validate_map(map: &Map, start: Position) -> Result<(), MapError> {
+    for idx in 0..map.tiles.len() {
+        let (x, y) = map.idx_xy(idx);
+        let on_border = x == 0 || y == 0 || x == map.width - 1 || y == map.height - 1;
+        if on_border && map.tiles[idx] != TileType::Wall {
+            return Err(MapError::OpenBorder(Position::new(x, y)));
+        }
+    }
+
+    let reachable = flood_fill(map, start)?;
+
+    for (i, room) in map.rooms.iter().enumerate() {
+        let (center_x, center_y) = room.center();
+        if !reachable[map.xy_idx(center_x, center_y)] {
+            return Err(MapError::UnreachableRoom(i));
+        }
+    }
+
+    let stairs: Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| map.tiles[*idx] == TileType::DownStairs)
+        .collect();
+    if stairs.is_empty() {
+        return Err(MapError::NoStairs);
+    }
+    for idx in stairs {
+        if !reachable[idx] {
+            return Err(MapError::UnreachableStairs(Position::from(map.idx_xy(idx))));
+        }
+    }
+    Ok(())
+}
+
+/// Return which tiles can be walked to from the start, moving in the same eight directions as
+/// entities do. Blocking entities are not taken into account.
+fn flood_fill(map: &Map, start: Position) -> Result<Vec<bool>, MapError> {
+    let start_idx = map.xy_idx(start.x, start.y);
+    if !map.tiles[start_idx].is_walkable() {
+        return Err(MapError::BlockedStart(start));
+    }
+
+    let mut reachable = vec![false; map.tiles.len()];
+    reachable[start_idx] = true;
+    let mut queue = VecDeque::from(vec![start]);
+    while let Some(pos) = queue.pop_front() {
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let next = pos + Position::new(dx, dy);
+                if next.x < 0 || next.y < 0 || next.x >= map.width || next.y >= map.height {
+                    continue;
+                }
+                let idx = map.xy_idx(next.x, next.y);
+                if !reachable[idx] && map.tiles[idx].is_walkable() {
+                    reachable[idx] = true;
+                    queue.push_back(next);
+                }
+            }
+        }
+    }
+    Ok(reachable)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map_builders::random_builder;
+    use crate::tile_registry::{load_tiles, TILES_FILE};
+
+    /// Panic if any map generated with the given seeds, at the first few depths, is not valid
+    fn check_seeds(seeds: std::ops::Range<u64>) {
+        load_tiles(TILES_FILE).expect("Unable to load the tiles");
+        for depth in 1..=4 {
+            for seed in seeds.clone() {
+                let mut builder = random_builder(80, 43, depth, seed);
+                builder.build();
+                if let Err(e) = validate_map(&builder.get_map(), builder.get_starting_position()) {
+                    panic!("Invalid map at depth {} with seed {}: {}", depth, seed, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn generated_maps_are_valid() {
+        check_seeds(0..200);
+    }
+
+    /// Too slow to run every time, run it with `cargo test --release -- --ignored` after changing
+    /// the map builders
+    #[test]
+    #[ignore]
+    fn many_generated_maps_are_valid() {
+        check_seeds(0..3000);
+    }
+}
@@ -1,6 +1,9 @@
 use crate::{Blocking, Map, Position};
 use specs::prelude::*;
 
+/// System that rebuilds the spatial index of the map (blocked tiles and the entities in each tile)
+/// from scratch. Moving entities keep it up to date, so this is only needed when a map is created or
+/// loaded.
 pub struct MapManagementSystem {}
 
 impl<'a> System<'a> for MapManagementSystem {
@@ -12,20 +15,51 @@ impl<'a> System<'a> for MapManagementSystem {
     );
 
     fn run(&mut self, (mut map, pos, block, entities): Self::SystemData) {
-        map.compute_blocked();
-        map.clear_entities();
+        index_entities(&mut map, &pos, &block, &entities);
+    }
+}
+
+fn index_entities(
+    map: &mut Map,
+    pos: &ReadStorage<Position>,
+    block: &ReadStorage<Blocking>,
+    entities: &Entities,
+) {
+    map.compute_blocked();
+    map.clear_entities();
 
-        // TODO: Also update blocked tiles when any blocking entity moves, not just once per tick, as
-        // many entities move in a tick and use this data
-        for (pos, ent) in (&pos, &entities).join() {
-            let idx = map.xy_idx(pos.x, pos.y);
+    for (pos, ent) in (pos, entities).join() {
+        map.add_entity(ent, *pos, block.get(ent).is_some());
+    }
+}
 
-            // If the entity has Blocking, update the map
-            if let Some(_) = block.get(ent) {
-                map.blocked[idx] = true;
-            }
+/// Panic if the spatial index of the map is not the same as rebuilding it from scratch. It's slow,
+/// so it's meant to be used only in debug builds.
+pub fn check_spatial_index(ecs: &World) {
+    let map = ecs.fetch::<Map>();
+    let mut expected = (*map).clone();
+    index_entities(
+        &mut expected,
+        &ecs.read_storage::<Position>(),
+        &ecs.read_storage::<Blocking>(),
+        &ecs.entities(),
+    );
 
-            map.tile_entities[idx].push(ent);
-        }
+    for idx in 0..map.tiles.len() {
+        let (x, y) = map.idx_xy(idx);
+        assert_eq!(
+            map.blocked[idx], expected.blocked[idx],
+            "Blocked tile out of date at ({}, {})",
+            x, y
+        );
+        let mut actual_entities = map.tile_entities[idx].clone();
+        let mut expected_entities = expected.tile_entities[idx].clone();
+        actual_entities.sort();
+        expected_entities.sort();
+        assert_eq!(
+            actual_entities, expected_entities,
+            "Tile entities out of date at ({}, {})",
+            x, y
+        );
     }
 }
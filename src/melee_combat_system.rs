@@ -0,0 +1,70 @@
+use crate::components::*;
+use crate::gamelog::GameLog;
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use std::cmp::max;
+
+/// System that resolves all melee attacks that entities want to perform this turn
+pub struct MeleeCombatSystem {}
+
+impl<'a> System<'a> for MeleeCombatSystem {
+    type SystemData = (
+        WriteExpect<'a, GameLog>,
+        ReadExpect<'a, Entity>, // Player entity
+        Entities<'a>,
+        WriteStorage<'a, WantsToMelee>,
+        ReadStorage<'a, Name>,
+        ReadStorage<'a, Fighter>,
+        WriteStorage<'a, SufferDamage>,
+    );
+
+    fn run(
+        &mut self,
+        (mut log, player_ent, entities, mut wants_melee, names, fighters, mut damage): Self::SystemData,
+    ) {
+        for (ent, wants_melee, name, fighter) in (&entities, &wants_melee, &names, &fighters).join()
+        {
+            // Dead entities can't attack, even if they wanted to earlier this turn
+            if fighter.hp <= 0 {
+                continue;
+            }
+            let target = wants_melee.target;
+            if let Some(target_fighter) = fighters.get(target) {
+                if target_fighter.hp <= 0 {
+                    continue;
+                }
+                let target_name = match names.get(target) {
+                    Some(n) => n.name.clone(),
+                    None => String::from("enemy"),
+                };
+                let damage_dealt = max(0, fighter.attack - target_fighter.defense);
+                if damage_dealt == 0 {
+                    log.info(format!(
+                        "{} is unable to hurt {}.",
+                        &name.name, &target_name
+                    ));
+                } else {
+                    // Highlight the damage the player takes
+                    let color = if target == *player_ent {
+                        RGB::named(RED)
+                    } else if ent == *player_ent {
+                        RGB::named(WHITE)
+                    } else {
+                        RGB::named(GRAY)
+                    };
+                    log.add(
+                        format!(
+                            "{} hits {} for {} hp.",
+                            &name.name, &target_name, damage_dealt
+                        ),
+                        color,
+                    );
+                    SufferDamage::new_damage(&mut damage, target, damage_dealt);
+                }
+            }
+        }
+
+        // All attacks have been resolved
+        wants_melee.clear();
+    }
+}
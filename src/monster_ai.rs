@@ -3,56 +3,212 @@ use super::{Map, Position, RandomNumberGenerator};
 use bracket_lib::prelude::*;
 use specs::prelude::*;
 
+/// Monsters flee when their hp falls to this percentage of their max hp
+const FLEE_HP_PERCENT: i32 = 25;
+/// Turns a monster keeps searching for the player after losing sight of them
+const SEARCH_TURNS: i32 = 10;
+/// Idle monsters start wandering with a chance of 1 in this every turn
+const IDLE_BOREDOM: i32 = 4;
+/// Wandering monsters stop with a chance of 1 in this every turn
+const WANDER_TIREDNESS: i32 = 20;
+/// How far ahead fleeing monsters look for an escape
+const FLEE_DEPTH: f32 = 20.0;
+
 /// System that controls monster AI
 pub struct MonsterAISystem {}
 
 impl<'a> System<'a> for MonsterAISystem {
     type SystemData = (
-        ReadExpect<'a, Map>,
+        WriteExpect<'a, Map>,
+        WriteExpect<'a, RandomNumberGenerator>,
         ReadExpect<'a, Position>, // Player position
+        ReadExpect<'a, Entity>,   // Player entity
+        Entities<'a>,
         ReadStorage<'a, Monster>,
-        ReadStorage<'a, Name>,
+        ReadStorage<'a, Blocking>,
+        ReadStorage<'a, MyTurn>,
+        ReadStorage<'a, Fighter>,
+        WriteStorage<'a, Ai>,
         WriteStorage<'a, Position>,
         WriteStorage<'a, Vision>,
+        WriteStorage<'a, WantsToMelee>,
     );
 
-    fn run(&mut self, (map, player_pos, monster, name, mut pos, mut vision): Self::SystemData) {
-        let mut rng = RandomNumberGenerator::new();
-        for (_monster, name, pos, vision) in (&monster, &name, &mut pos, &mut vision).join() {
-            // If the player is visible, chase it or attack if in range
-            if vision.visible.contains(&*player_pos) {
-                let distance = DistanceAlg::Pythagoras.distance2d(
-                    Point::new(pos.x, pos.y),
-                    Point::new(player_pos.x, player_pos.y),
-                );
-                if distance < 1.5 {
-                    // TODO: Attack!
-                    console::log(format!("{} spits at you.", name.name));
-                    return;
+    fn run(
+        &mut self,
+        (
+            mut map,
+            mut rng,
+            player_pos,
+            player_ent,
+            entities,
+            monster,
+            blockers,
+            my_turn,
+            fighters,
+            mut ai,
+            mut pos,
+            mut vision,
+            mut wants_melee,
+        ): Self::SystemData,
+    ) {
+        let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+        let mut opened_door = false;
+        // Only the monsters that have enough energy act
+        for (ent, _monster, _turn, ai, pos, vision) in (
+            &entities,
+            &monster,
+            &my_turn,
+            &mut ai,
+            &mut pos,
+            &mut vision,
+        )
+            .join()
+        {
+            let pos_idx = map.xy_idx(pos.x, pos.y);
+            let sees_player = vision.visible.contains(player_idx);
+            let badly_hurt = match fighters.get(ent) {
+                Some(fighter) => fighter.hp * 100 <= fighter.max_hp * FLEE_HP_PERCENT,
+                None => false,
+            };
+            let distance_to_player = |idx: usize| {
+                let (x, y) = map.idx_xy(idx);
+                DistanceAlg::Pythagoras
+                    .distance2d(Point::new(x, y), Point::new(player_pos.x, player_pos.y))
+            };
+            let adjacent = distance_to_player(pos_idx) < 1.5;
+
+            // Decide what to do
+            if sees_player {
+                ai.last_known_player_pos = Some(*player_pos);
+                ai.state = if badly_hurt {
+                    AiState::Flee
                 } else {
-                    // Chase player
-                    let path = a_star_search(
-                        map.xy_idx(pos.x, pos.y) as i32,
-                        map.xy_idx(player_pos.x, player_pos.y) as i32,
-                        &*map,
-                    );
-                    if path.success && path.steps.len() > 1 {
-                        // TODO: encapsulate actual movement in another function
-                        *pos = Position::from(map.idx_xy(path.steps[1]));
-                        vision.recompute = true;
+                    AiState::Hunt
+                };
+            } else {
+                match ai.state {
+                    AiState::Hunt => {
+                        ai.state = AiState::Search;
+                        ai.search_turns = SEARCH_TURNS;
+                    }
+                    // Once out of sight, stay hidden
+                    AiState::Flee => ai.state = AiState::Idle,
+                    AiState::Search if ai.search_turns <= 0 => {
+                        ai.state = AiState::Wander;
+                        ai.last_known_player_pos = None;
                     }
+                    AiState::Idle if rng.range(0, IDLE_BOREDOM) == 0 => ai.state = AiState::Wander,
+                    AiState::Wander if rng.range(0, WANDER_TIREDNESS) == 0 => {
+                        ai.state = AiState::Idle
+                    }
+                    _ => {}
                 }
-            } else {
-                // If the player is not visible, pick a random available direction and move in that direction
-                let pos_idx = map.xy_idx(pos.x, pos.y);
-                let available_exits = map.get_available_exits(pos_idx);
-                let new_pos = rng.random_slice_entry(&available_exits);
-                if let Some(new_pos) = new_pos {
-                    let (x, y) = map.idx_xy(new_pos.0);
-                    *pos = Position::new(x, y);
-                    vision.recompute = true;
+            }
+
+            // Pick the tile to move to, if any
+            let mut attack = false;
+            let destination = match ai.state {
+                AiState::Idle => None,
+                AiState::Wander => random_exit(&map, &mut rng, pos_idx),
+                AiState::Hunt => {
+                    if adjacent {
+                        attack = true;
+                        None
+                    } else {
+                        path_step(&map, pos_idx, player_idx)
+                    }
+                }
+                AiState::Search => {
+                    ai.search_turns -= 1;
+                    match ai.last_known_player_pos {
+                        Some(last_pos) if last_pos != *pos => {
+                            let step = path_step(&map, pos_idx, map.xy_idx(last_pos.x, last_pos.y));
+                            if step.is_none() {
+                                // There is no way there, so look around instead
+                                ai.last_known_player_pos = None;
+                            }
+                            step
+                        }
+                        _ => {
+                            // Nothing found where the player was seen, so look around
+                            ai.last_known_player_pos = None;
+                            random_exit(&map, &mut rng, pos_idx)
+                        }
+                    }
+                }
+                AiState::Flee => {
+                    let flee_map =
+                        DijkstraMap::new(map.width, map.height, &[player_idx], &*map, FLEE_DEPTH);
+                    // Only run if it actually gets the monster farther from the player
+                    let escape = DijkstraMap::find_highest_exit(&flee_map, pos_idx, &*map)
+                        .filter(|idx| distance_to_player(*idx) > distance_to_player(pos_idx));
+                    // Cornered monsters fight back
+                    attack = escape.is_none() && adjacent;
+                    escape
                 }
+            };
+
+            if attack {
+                wants_melee
+                    .insert(
+                        ent,
+                        WantsToMelee {
+                            target: *player_ent,
+                        },
+                    )
+                    .expect("Unable to insert attack");
+            }
+            if let Some(idx) = destination {
+                opened_door |= step_monster(&mut map, ent, pos, vision, &blockers, idx);
+            }
+        }
+
+        // Opening a door changes what everyone can see
+        if opened_door {
+            for vision in (&mut vision).join() {
+                vision.recompute = true;
             }
         }
     }
 }
+
+/// Return the next tile of the shortest path between two tiles, if there is a path
+fn path_step(map: &Map, from: usize, to: usize) -> Option<usize> {
+    let path = a_star_search(from as i32, to as i32, map);
+    if path.success && path.steps.len() > 1 {
+        Some(path.steps[1])
+    } else {
+        None
+    }
+}
+
+/// Return a random tile next to the given one that can be walked to
+fn random_exit(map: &Map, rng: &mut RandomNumberGenerator, idx: usize) -> Option<usize> {
+    let available_exits = map.get_available_exits(idx);
+    rng.random_slice_entry(&available_exits)
+        .map(|(exit_idx, _cost)| *exit_idx)
+}
+
+/// Move a monster to the given tile, or open it if it's a closed door. Returns true if a door was
+/// opened.
+fn step_monster(
+    map: &mut Map,
+    ent: Entity,
+    pos: &mut Position,
+    vision: &mut Vision,
+    blockers: &ReadStorage<Blocking>,
+    idx: usize,
+) -> bool {
+    if map.try_open_door(idx) {
+        return true;
+    }
+    // Another monster may have moved there in this same turn
+    if !map.blocked[idx] {
+        let new_pos = Position::from(map.idx_xy(idx));
+        map.move_entity(ent, *pos, new_pos, blockers.get(ent).is_some());
+        *pos = new_pos;
+        vision.recompute = true;
+    }
+    false
+}
@@ -1,35 +1,46 @@
 use crate::components::*;
-use crate::{Map, TurnState};
+use crate::gamelog::GameLog;
+use crate::map::TileType;
+use crate::saveload_system::{load_game, save_game};
+use crate::{MainMenuSelection, Map, TurnState};
 use bracket_lib::prelude::*;
 use specs::prelude::*;
+use std::cmp::min;
+use std::collections::HashSet;
 
 /// Try moving players by the given diff and return the new turn state (Running if the
 /// player turn was successful or Paused if no turn was taken)
 pub fn try_move_player(dx: i32, dy: i32, ecs: &mut World) -> TurnState {
     let mut moved = false;
-    let map = ecs.fetch::<Map>();
+    let mut opened_door = false;
+    let mut map = ecs.write_resource::<Map>();
     let mut positions = ecs.write_storage::<Position>();
     let fighters = ecs.read_storage::<Fighter>();
     let players = ecs.read_storage::<Player>();
+    let blockers = ecs.read_storage::<Blocking>();
+    let entities = ecs.entities();
+    let mut wants_melee = ecs.write_storage::<WantsToMelee>();
     let mut player_pos_res = ecs.write_resource::<Position>();
-    for (pos, _player) in (&mut positions, &players).join() {
+    for (ent, pos, _player) in (&entities, &mut positions, &players).join() {
         let new_pos = *pos + Position::new(dx, dy);
         let new_pos_idx = map.xy_idx(new_pos.x, new_pos.y);
         if map.in_bounds(Point::new(new_pos.x, new_pos.y)) {
-            for ent in map.tile_entities[new_pos_idx].iter() {
-                if let Some(target) = fighters.get(*ent) {
-                    // TODO: Attack!
-                    let mut name = String::from("enemy");
-                    if let Some(s) = ecs.read_storage::<Name>().get(*ent) {
-                        name = s.name.clone();
-                    }
-                    console::log(format!("You slap {} in the face", name));
+            for target in map.tile_entities[new_pos_idx].iter() {
+                if fighters.get(*target).is_some() {
+                    // Bumping into a fighter attacks it
+                    wants_melee
+                        .insert(ent, WantsToMelee { target: *target })
+                        .expect("Unable to insert attack");
                     return TurnState::Running;
                 }
             }
 
-            // Move player
-            if !map.blocked[new_pos_idx] {
+            // Bumping into a closed door opens it
+            if map.try_open_door(new_pos_idx) {
+                opened_door = true;
+            } else if !map.blocked[new_pos_idx] {
+                // Move player
+                map.move_entity(ent, *pos, new_pos, blockers.get(ent).is_some());
                 *pos = new_pos;
                 // Also update the resource
                 *player_pos_res = new_pos;
@@ -38,6 +49,15 @@ pub fn try_move_player(dx: i32, dy: i32, ecs: &mut World) -> TurnState {
         }
     }
 
+    // Opening a door changes what everyone can see
+    if opened_door {
+        ecs.write_resource::<GameLog>().info("You open the door.");
+        for vision in (&mut ecs.write_storage::<Vision>()).join() {
+            vision.recompute = true;
+        }
+        return TurnState::Running;
+    }
+
     // If the player moved, we need to recompute FOV
     if moved {
         let mut visions = ecs.write_storage::<Vision>();
@@ -49,30 +69,361 @@ pub fn try_move_player(dx: i32, dy: i32, ecs: &mut World) -> TurnState {
     TurnState::Paused
 }
 
+/// Go down the stairs if the player is standing on them
+pub fn try_descend(ecs: &mut World) -> TurnState {
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Position>();
+    if map.tiles[map.xy_idx(player_pos.x, player_pos.y)] == TileType::DownStairs {
+        TurnState::NextLevel
+    } else {
+        ecs.write_resource::<GameLog>()
+            .info("There is no way down from here.");
+        TurnState::Paused
+    }
+}
+
+/// Close the open doors next to the player, as long as there is nothing in the way
+pub fn try_close_door(ecs: &mut World) -> TurnState {
+    let mut closed_door = false;
+    {
+        let mut map = ecs.write_resource::<Map>();
+        let player_pos = ecs.fetch::<Position>();
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let pos = *player_pos + Position::new(dx, dy);
+                let idx = map.xy_idx(pos.x, pos.y);
+                if map.tiles[idx] == TileType::DoorOpen && map.tile_entities[idx].is_empty() {
+                    map.set_tile(idx, TileType::DoorClosed);
+                    closed_door = true;
+                }
+            }
+        }
+    }
+
+    if closed_door {
+        ecs.write_resource::<GameLog>().info("You close the door.");
+        for vision in (&mut ecs.write_storage::<Vision>()).join() {
+            vision.recompute = true;
+        }
+        TurnState::Running
+    } else {
+        ecs.write_resource::<GameLog>()
+            .info("There is no open door you can close next to you.");
+        TurnState::Paused
+    }
+}
+
+/// Pick up an item from the tile the player is standing on
+pub fn try_pickup_item(ecs: &mut World) -> TurnState {
+    let map = ecs.fetch::<Map>();
+    let player_pos = ecs.fetch::<Position>();
+    let player = ecs.fetch::<Entity>();
+    let items = ecs.read_storage::<Item>();
+    let mut wants_pickup = ecs.write_storage::<WantsToPickupItem>();
+
+    let idx = map.xy_idx(player_pos.x, player_pos.y);
+    for ent in map.tile_entities[idx].iter() {
+        if items.get(*ent).is_some() {
+            wants_pickup
+                .insert(
+                    *player,
+                    WantsToPickupItem {
+                        collected_by: *player,
+                        item: *ent,
+                    },
+                )
+                .expect("Unable to insert pickup");
+            return TurnState::Running;
+        }
+    }
+
+    ecs.write_resource::<GameLog>()
+        .info("There is nothing here to pick up.");
+    TurnState::Paused
+}
+
+/// Use an item from the player's backpack. Items that need a target and don't have one yet start
+/// the targeting mode instead.
+pub fn use_item(ecs: &mut World, item: Entity, target: Option<Position>) -> TurnState {
+    if target.is_none() {
+        if let Some(ranged) = ecs.read_storage::<Ranged>().get(item) {
+            return TurnState::ShowTargeting {
+                range: ranged.range,
+                item,
+                cursor: *ecs.fetch::<Position>(),
+            };
+        }
+    }
+
+    let player = *ecs.fetch::<Entity>();
+    ecs.write_storage::<WantsToUseItem>()
+        .insert(player, WantsToUseItem { item, target })
+        .expect("Unable to insert item use");
+    TurnState::Running
+}
+
+/// Return the monsters the player can see
+pub fn monsters_in_view(ecs: &World) -> HashSet<Entity> {
+    let player = ecs.fetch::<Entity>();
+    let map = ecs.fetch::<Map>();
+    let visions = ecs.read_storage::<Vision>();
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let monsters = ecs.read_storage::<Monster>();
+    match visions.get(*player) {
+        Some(vision) => (&entities, &positions, &monsters)
+            .join()
+            .filter(|(_ent, pos, _monster)| vision.visible.contains(map.xy_idx(pos.x, pos.y)))
+            .map(|(ent, _pos, _monster)| ent)
+            .collect(),
+        None => HashSet::new(),
+    }
+}
+
+/// Return true if the player can see any monster
+pub fn hostile_in_view(ecs: &World) -> bool {
+    !monsters_in_view(ecs).is_empty()
+}
+
+/// Skip the player turn. Waiting with no enemies in sight recovers some health.
+pub fn try_wait(ecs: &mut World) -> TurnState {
+    if !hostile_in_view(ecs) {
+        let player = ecs.fetch::<Entity>();
+        let mut fighters = ecs.write_storage::<Fighter>();
+        if let Some(fighter) = fighters.get_mut(*player) {
+            fighter.hp = min(fighter.max_hp, fighter.hp + 1);
+        }
+    }
+    TurnState::Running
+}
+
+/// Take one turn of rest, stopping when fully healed, when an enemy comes into view or when any
+/// key is pressed
+pub fn rest_step(ecs: &mut World, ctx: &mut BTerm) -> TurnState {
+    if ctx.key.is_some() {
+        return TurnState::Paused;
+    }
+    if hostile_in_view(ecs) {
+        ecs.write_resource::<GameLog>()
+            .info("You can't rest with enemies nearby.");
+        return TurnState::Paused;
+    }
+    let healed = {
+        let player = ecs.fetch::<Entity>();
+        let fighters = ecs.read_storage::<Fighter>();
+        match fighters.get(*player) {
+            Some(fighter) => fighter.hp >= fighter.max_hp,
+            None => true,
+        }
+    };
+    if healed {
+        ecs.write_resource::<GameLog>().info("You feel rested.");
+        return TurnState::Paused;
+    }
+    try_wait(ecs)
+}
+
+/// Take one step towards the closest unexplored tile, stopping when there is nothing left to
+/// explore, when an enemy comes into view or when any key is pressed
+pub fn explore_step(ecs: &mut World, ctx: &mut BTerm) -> TurnState {
+    if ctx.key.is_some() {
+        return TurnState::Paused;
+    }
+    if hostile_in_view(ecs) {
+        ecs.write_resource::<GameLog>()
+            .info("You stop exploring, there are enemies nearby.");
+        return TurnState::Paused;
+    }
+
+    let next_step = {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Position>();
+        let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+        let unexplored: Vec<usize> = (0..map.tiles.len())
+            .filter(|idx| !map.explored[*idx] && map.tiles[*idx].is_walkable())
+            .collect();
+        // No path can be longer than visiting every tile diagonally
+        let max_depth = map.tiles.len() as f32 * std::f32::consts::SQRT_2;
+        let dijkstra_map = DijkstraMap::new(map.width, map.height, &unexplored, &*map, max_depth);
+        if dijkstra_map.map[player_idx] == f32::MAX {
+            None
+        } else {
+            DijkstraMap::find_lowest_exit(&dijkstra_map, player_idx, &*map).map(|idx| {
+                let (x, y) = map.idx_xy(idx);
+                (x - player_pos.x, y - player_pos.y)
+            })
+        }
+    };
+
+    match next_step {
+        Some((dx, dy)) => try_move_player(dx, dy, ecs),
+        None => {
+            ecs.write_resource::<GameLog>()
+                .info("There is nothing left to explore here.");
+            TurnState::Paused
+        }
+    }
+}
+
+/// Start travelling to the given tile, which must be an explored floor tile
+pub fn start_travel(ecs: &mut World, target: Position) -> TurnState {
+    let valid = {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Position>();
+        if map.in_bounds(Point::new(target.x, target.y)) && target != *player_pos {
+            let idx = map.xy_idx(target.x, target.y);
+            map.explored[idx] && map.tiles[idx].is_walkable()
+        } else {
+            false
+        }
+    };
+    if !valid {
+        ecs.write_resource::<GameLog>()
+            .info("You don't know how to get there.");
+        return TurnState::Paused;
+    }
+    TurnState::Travelling {
+        target,
+        monsters_seen: monsters_in_view(ecs),
+    }
+}
+
+/// Take one step along the shortest path to the travel target, stopping when the target is
+/// reached, when a new monster comes into view, when the path is blocked or when any key is
+/// pressed
+pub fn travel_step(
+    ecs: &mut World,
+    ctx: &mut BTerm,
+    target: Position,
+    monsters_seen: &HashSet<Entity>,
+) -> TurnState {
+    if ctx.key.is_some() || *ecs.fetch::<Position>() == target {
+        return TurnState::Paused;
+    }
+    if !monsters_in_view(ecs).is_subset(monsters_seen) {
+        ecs.write_resource::<GameLog>()
+            .info("You stop travelling, a monster comes into view.");
+        return TurnState::Paused;
+    }
+
+    let next_step = {
+        let map = ecs.fetch::<Map>();
+        let player_pos = ecs.fetch::<Position>();
+        let blockers = ecs.read_storage::<Blocking>();
+        // Plan the path through the tiles only, so that travel stops when something gets in the way
+        // instead of quietly taking a detour around it
+        let mut tiles_map = (*map).clone();
+        tiles_map.compute_blocked();
+        let path = a_star_search(
+            map.xy_idx(player_pos.x, player_pos.y),
+            map.xy_idx(target.x, target.y),
+            &tiles_map,
+        );
+        if path.success && path.steps.len() > 1 {
+            let next_idx = path.steps[1];
+            let blocked = map.tile_entities[next_idx]
+                .iter()
+                .any(|ent| blockers.get(*ent).is_some());
+            if blocked {
+                None
+            } else {
+                let (x, y) = map.idx_xy(next_idx);
+                Some((x - player_pos.x, y - player_pos.y))
+            }
+        } else {
+            None
+        }
+    };
+
+    match next_step {
+        Some((dx, dy)) => try_move_player(dx, dy, ecs),
+        None => {
+            ecs.write_resource::<GameLog>()
+                .info("Something blocks your way.");
+            TurnState::Paused
+        }
+    }
+}
+
+/// Return the direction a key moves the player (or any cursor) in, if it's a movement key
+pub fn movement_direction(key: VirtualKeyCode) -> Option<(i32, i32)> {
+    match key {
+        // Orthogonal movement
+        VirtualKeyCode::Left | VirtualKeyCode::Numpad4 | VirtualKeyCode::H => Some((-1, 0)),
+        VirtualKeyCode::Right | VirtualKeyCode::Numpad6 | VirtualKeyCode::L => Some((1, 0)),
+        VirtualKeyCode::Down | VirtualKeyCode::Numpad2 | VirtualKeyCode::J => Some((0, 1)),
+        VirtualKeyCode::Up | VirtualKeyCode::Numpad8 | VirtualKeyCode::K => Some((0, -1)),
+        // Diagonal movement
+        VirtualKeyCode::Numpad1 | VirtualKeyCode::B => Some((-1, 1)),
+        VirtualKeyCode::Numpad3 | VirtualKeyCode::N => Some((1, 1)),
+        VirtualKeyCode::Numpad7 | VirtualKeyCode::Y => Some((-1, -1)),
+        VirtualKeyCode::Numpad9 | VirtualKeyCode::U => Some((1, -1)),
+        _ => None,
+    }
+}
+
 /// Take player input and return the new turn state
 pub fn player_input(ecs: &mut World, ctx: &mut BTerm) -> TurnState {
+    // Clicking on a known tile travels there
+    if ctx.left_click {
+        return start_travel(ecs, Position::from(ctx.mouse_pos()));
+    }
+
     // Player movement
+    if let Some((dx, dy)) = ctx.key.and_then(movement_direction) {
+        return try_move_player(dx, dy, ecs);
+    }
+
     match ctx.key {
         None => TurnState::Paused, // No key is being pressed
         Some(key) => match key {
-            // Orthogonal movement
-            VirtualKeyCode::Left | VirtualKeyCode::Numpad4 | VirtualKeyCode::H => {
-                try_move_player(-1, 0, ecs)
-            }
-            VirtualKeyCode::Right | VirtualKeyCode::Numpad6 | VirtualKeyCode::L => {
-                try_move_player(1, 0, ecs)
-            }
-            VirtualKeyCode::Down | VirtualKeyCode::Numpad2 | VirtualKeyCode::J => {
-                try_move_player(0, 1, ecs)
+            // Stairs
+            VirtualKeyCode::Period if ctx.shift => try_descend(ecs),
+            // Waiting and multi-turn commands
+            VirtualKeyCode::Period | VirtualKeyCode::Numpad5 => try_wait(ecs),
+            VirtualKeyCode::R => TurnState::Resting,
+            VirtualKeyCode::O => TurnState::AutoExploring,
+            // Doors
+            VirtualKeyCode::C => try_close_door(ecs),
+            // Items
+            VirtualKeyCode::G => try_pickup_item(ecs),
+            VirtualKeyCode::I => TurnState::ShowInventory,
+            // Look around
+            VirtualKeyCode::Semicolon => TurnState::Look {
+                cursor: *ecs.fetch::<Position>(),
+            },
+            // Message history
+            VirtualKeyCode::M => TurnState::ShowLog { scroll: 0 },
+            // Quick save and load
+            VirtualKeyCode::F5 => {
+                let result = save_game(ecs);
+                let mut log = ecs.write_resource::<GameLog>();
+                match result {
+                    Ok(()) => log.info("Game saved."),
+                    Err(e) => log.add(format!("Unable to save the game: {}", e), RGB::named(RED)),
+                }
+                TurnState::Paused
             }
-            VirtualKeyCode::Up | VirtualKeyCode::Numpad8 | VirtualKeyCode::K => {
-                try_move_player(0, -1, ecs)
+            // Save and go back to the main menu
+            VirtualKeyCode::Escape => match save_game(ecs) {
+                Ok(()) => TurnState::MainMenu {
+                    selected: MainMenuSelection::Continue,
+                },
+                Err(e) => {
+                    ecs.write_resource::<GameLog>()
+                        .add(format!("Unable to save the game: {}", e), RGB::named(RED));
+                    TurnState::Paused
+                }
+            },
+            VirtualKeyCode::F9 => {
+                let result = load_game(ecs);
+                let mut log = ecs.write_resource::<GameLog>();
+                match result {
+                    Ok(()) => log.info("Game loaded."),
+                    Err(e) => log.add(format!("Unable to load the game: {}", e), RGB::named(RED)),
+                }
+                TurnState::Paused
             }
-            // Diagonal movement
-            VirtualKeyCode::Numpad1 | VirtualKeyCode::B => try_move_player(-1, 1, ecs),
-            VirtualKeyCode::Numpad3 | VirtualKeyCode::N => try_move_player(1, 1, ecs),
-            VirtualKeyCode::Numpad7 | VirtualKeyCode::Y => try_move_player(-1, -1, ecs),
-            VirtualKeyCode::Numpad9 | VirtualKeyCode::U => try_move_player(1, -1, ecs),
             _ => TurnState::Paused,
         },
     }
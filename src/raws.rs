@@ -0,0 +1,449 @@
+use crate::initiative_system::NORMAL_SPEED;
+use bracket_lib::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::sync::OnceLock;
+
+pub const RAWS_FILE: &str = "res/raws.json";
+
+static RAWS: OnceLock<Raws> = OnceLock::new();
+
+/// The monster and item templates, indexed by name
+pub struct Raws {
+    pub monsters: HashMap<String, MonsterTemplate>,
+    pub items: HashMap<String, ItemTemplate>,
+    pub spawn_table: Vec<SpawnEntry>,
+}
+
+pub struct MonsterTemplate {
+    pub name: String,
+    pub glyph: u8,
+    pub fg: RGB,
+    pub bg: RGB,
+    pub vision_range: i32,
+    pub speed: i32,
+    /// Stats at depth 1, monsters get tougher deeper in the dungeon
+    pub hp: i32,
+    pub attack: i32,
+    pub defense: i32,
+    pub blocking: bool,
+    pub leaves_corpse: bool,
+}
+
+pub struct ItemTemplate {
+    pub name: String,
+    pub glyph: u8,
+    pub fg: RGB,
+    pub bg: RGB,
+    pub consumable: bool,
+    pub healing: Option<i32>,
+    pub damage: Option<i32>,
+    /// Items with a range need a target to be used
+    pub range: Option<i32>,
+}
+
+/// How often a monster or item is spawned compared to the rest, in the levels between min_depth and
+/// max_depth (inclusive)
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SpawnEntry {
+    pub name: String,
+    pub weight: i32,
+    #[serde(default = "default_min_depth")]
+    pub min_depth: i32,
+    pub max_depth: Option<i32>,
+}
+
+/// The raws file as it's written
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawFile {
+    monsters: Vec<RawMonster>,
+    items: Vec<RawItem>,
+    spawn_table: Vec<SpawnEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawMonster {
+    name: String,
+    glyph: char,
+    fg: String,
+    #[serde(default = "default_bg")]
+    bg: String,
+    vision_range: i32,
+    #[serde(default = "default_speed")]
+    speed: i32,
+    stats: RawStats,
+    #[serde(default = "default_true")]
+    blocking: bool,
+    #[serde(default = "default_true")]
+    leaves_corpse: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawStats {
+    hp: i32,
+    attack: i32,
+    defense: i32,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawItem {
+    name: String,
+    glyph: char,
+    fg: String,
+    #[serde(default = "default_bg")]
+    bg: String,
+    #[serde(default)]
+    consumable: bool,
+    healing: Option<i32>,
+    damage: Option<i32>,
+    range: Option<i32>,
+}
+
+fn default_bg() -> String {
+    String::from("#000000")
+}
+
+fn default_speed() -> i32 {
+    NORMAL_SPEED
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_min_depth() -> i32 {
+    1
+}
+
+#[derive(Debug)]
+pub enum RawsError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// A problem with an entry, described by the message
+    Invalid {
+        entry: String,
+        message: String,
+    },
+}
+
+impl fmt::Display for RawsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RawsError::Io(e) => write!(f, "I/O error: {}", e),
+            RawsError::Json(e) => write!(f, "malformed raws file: {}", e),
+            RawsError::Invalid { entry, message } => write!(f, "{}: {}", entry, message),
+        }
+    }
+}
+
+impl From<io::Error> for RawsError {
+    fn from(e: io::Error) -> Self {
+        RawsError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for RawsError {
+    fn from(e: serde_json::Error) -> Self {
+        RawsError::Json(e)
+    }
+}
+
+/// Checks the fields of a single entry, building the errors with its description
+struct EntryValidator {
+    entry: String,
+}
+
+impl EntryValidator {
+    fn new(kind: &str, index: usize, name: &str) -> Self {
+        Self {
+            entry: format!("{} #{} ({:?})", kind, index, name),
+        }
+    }
+
+    fn error(&self, message: String) -> RawsError {
+        RawsError::Invalid {
+            entry: self.entry.clone(),
+            message,
+        }
+    }
+
+    fn color(&self, field: &str, code: &str) -> Result<RGB, RawsError> {
+        RGB::from_hex(code).map_err(|_| {
+            self.error(format!(
+                "{} is {:?}, expected an HTML colour code like \"#00ff00\"",
+                field, code
+            ))
+        })
+    }
+
+    fn at_least(&self, field: &str, value: i32, min: i32) -> Result<i32, RawsError> {
+        if value < min {
+            Err(self.error(format!("{} is {}, expected at least {}", field, value, min)))
+        } else {
+            Ok(value)
+        }
+    }
+}
+
+/// Parse and check the templates from the contents of a raws file
+fn parse_raws(json: &str) -> Result<Raws, RawsError> {
+    let raw_file: RawFile = serde_json::from_str(json)?;
+    let mut raws = Raws {
+        monsters: HashMap::new(),
+        items: HashMap::new(),
+        spawn_table: Vec::new(),
+    };
+
+    for (i, raw) in raw_file.monsters.into_iter().enumerate() {
+        let v = EntryValidator::new("monster", i, &raw.name);
+        if raw.name.is_empty() {
+            return Err(v.error(String::from("name is empty")));
+        }
+        if raws.monsters.contains_key(&raw.name) {
+            return Err(v.error(String::from("there is another monster with that name")));
+        }
+        let template = MonsterTemplate {
+            name: raw.name.clone(),
+            glyph: to_cp437(raw.glyph),
+            fg: v.color("fg", &raw.fg)?,
+            bg: v.color("bg", &raw.bg)?,
+            vision_range: v.at_least("vision_range", raw.vision_range, 1)?,
+            speed: v.at_least("speed", raw.speed, 1)?,
+            hp: v.at_least("stats.hp", raw.stats.hp, 1)?,
+            attack: v.at_least("stats.attack", raw.stats.attack, 0)?,
+            defense: v.at_least("stats.defense", raw.stats.defense, 0)?,
+            blocking: raw.blocking,
+            leaves_corpse: raw.leaves_corpse,
+        };
+        raws.monsters.insert(raw.name, template);
+    }
+
+    for (i, raw) in raw_file.items.into_iter().enumerate() {
+        let v = EntryValidator::new("item", i, &raw.name);
+        if raw.name.is_empty() {
+            return Err(v.error(String::from("name is empty")));
+        }
+        if raws.items.contains_key(&raw.name) || raws.monsters.contains_key(&raw.name) {
+            return Err(v.error(String::from(
+                "there is another monster or item with that name",
+            )));
+        }
+        if raw.range.is_some() && raw.damage.is_none() {
+            return Err(v.error(String::from("range is only used by items with damage")));
+        }
+        let template = ItemTemplate {
+            name: raw.name.clone(),
+            glyph: to_cp437(raw.glyph),
+            fg: v.color("fg", &raw.fg)?,
+            bg: v.color("bg", &raw.bg)?,
+            consumable: raw.consumable,
+            healing: raw
+                .healing
+                .map(|amount| v.at_least("healing", amount, 1))
+                .transpose()?,
+            damage: raw
+                .damage
+                .map(|amount| v.at_least("damage", amount, 1))
+                .transpose()?,
+            range: raw
+                .range
+                .map(|range| v.at_least("range", range, 1))
+                .transpose()?,
+        };
+        raws.items.insert(raw.name, template);
+    }
+
+    for (i, entry) in raw_file.spawn_table.into_iter().enumerate() {
+        let v = EntryValidator::new("spawn_table entry", i, &entry.name);
+        if !raws.monsters.contains_key(&entry.name) && !raws.items.contains_key(&entry.name) {
+            return Err(v.error(String::from("there is no monster or item with that name")));
+        }
+        v.at_least("weight", entry.weight, 1)?;
+        v.at_least("min_depth", entry.min_depth, 1)?;
+        if let Some(max_depth) = entry.max_depth {
+            v.at_least("max_depth", max_depth, entry.min_depth)?;
+        }
+        raws.spawn_table.push(entry);
+    }
+
+    Ok(raws)
+}
+
+/// Load the monster and item templates from a file. It has to be done once at startup, before
+/// anything is spawned. Calling it again checks the file but doesn't replace the templates.
+pub fn load_raws(path: &str) -> Result<(), RawsError> {
+    let raws = parse_raws(&fs::read_to_string(path)?)?;
+    let _ = RAWS.set(raws);
+    Ok(())
+}
+
+/// Return the loaded templates
+pub fn raws() -> &'static Raws {
+    RAWS.get().expect("The raws have not been loaded")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Return a small raws file, to be broken by each test
+    fn valid_raws() -> Value {
+        json!({
+            "monsters": [
+                {
+                    "name": "Orc",
+                    "glyph": "o",
+                    "fg": "#00FF00",
+                    "vision_range": 8,
+                    "stats": { "hp": 12, "attack": 4, "defense": 1 }
+                },
+                {
+                    "name": "Bat",
+                    "glyph": "b",
+                    "fg": "#FF4040",
+                    "vision_range": 8,
+                    "speed": 20,
+                    "stats": { "hp": 5, "attack": 3, "defense": 0 }
+                }
+            ],
+            "items": [
+                {
+                    "name": "Magic Missile Scroll",
+                    "glyph": ")",
+                    "fg": "#00FFFF",
+                    "consumable": true,
+                    "range": 6,
+                    "damage": 8
+                }
+            ],
+            "spawn_table": [
+                { "name": "Orc", "weight": 10 },
+                { "name": "Bat", "weight": 6, "max_depth": 4 },
+                { "name": "Magic Missile Scroll", "weight": 4, "min_depth": 2 }
+            ]
+        })
+    }
+
+    fn parse_error(json: &str) -> String {
+        match parse_raws(json) {
+            Ok(_) => panic!("The raws were parsed without errors"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn raws_file_is_valid() {
+        let json = fs::read_to_string(RAWS_FILE).expect("Unable to read the raws file");
+        assert!(parse_raws(&json).is_ok());
+        assert!(parse_raws(&valid_raws().to_string()).is_ok());
+    }
+
+    #[test]
+    fn malformed_json() {
+        assert_eq!(
+            parse_error("{\"monsters\": ["),
+            "malformed raws file: EOF while parsing a list at line 1 column 14"
+        );
+    }
+
+    #[test]
+    fn empty_name() {
+        let mut raws = valid_raws();
+        raws["monsters"][0]["name"] = json!("");
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "monster #0 (\"\"): name is empty"
+        );
+    }
+
+    #[test]
+    fn invalid_monster() {
+        let mut raws = valid_raws();
+        raws["monsters"][1]["speed"] = json!(0);
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "monster #1 (\"Bat\"): speed is 0, expected at least 1"
+        );
+
+        let mut raws = valid_raws();
+        raws["monsters"][0]["stats"]["hp"] = json!(-3);
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "monster #0 (\"Orc\"): stats.hp is -3, expected at least 1"
+        );
+    }
+
+    #[test]
+    fn duplicate_name() {
+        let mut raws = valid_raws();
+        raws["monsters"][1]["name"] = json!("Orc");
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "monster #1 (\"Orc\"): there is another monster with that name"
+        );
+
+        let mut raws = valid_raws();
+        raws["items"][0]["name"] = json!("Bat");
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "item #0 (\"Bat\"): there is another monster or item with that name"
+        );
+    }
+
+    #[test]
+    fn invalid_item() {
+        let mut raws = valid_raws();
+        raws["items"][0]["fg"] = json!("cyan");
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "item #0 (\"Magic Missile Scroll\"): fg is \"cyan\", expected an HTML colour code like \"#00ff00\""
+        );
+
+        let mut raws = valid_raws();
+        raws["items"][0].as_object_mut().unwrap().remove("damage");
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "item #0 (\"Magic Missile Scroll\"): range is only used by items with damage"
+        );
+
+        let mut raws = valid_raws();
+        raws["items"][0]["damage"] = json!(0);
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "item #0 (\"Magic Missile Scroll\"): damage is 0, expected at least 1"
+        );
+    }
+
+    #[test]
+    fn invalid_spawn_entry() {
+        let mut raws = valid_raws();
+        raws["spawn_table"][0]["name"] = json!("Dragon");
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "spawn_table entry #0 (\"Dragon\"): there is no monster or item with that name"
+        );
+
+        let mut raws = valid_raws();
+        raws["spawn_table"][2]["max_depth"] = json!(1);
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "spawn_table entry #2 (\"Magic Missile Scroll\"): max_depth is 1, expected at least 2"
+        );
+
+        let mut raws = valid_raws();
+        raws["spawn_table"][1]["weight"] = json!(0);
+        assert_eq!(
+            parse_error(&raws.to_string()),
+            "spawn_table entry #1 (\"Bat\"): weight is 0, expected at least 1"
+        );
+    }
+}
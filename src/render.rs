@@ -1,25 +1,25 @@
-use super::components::{Position, Renderable};
-use super::map::{Map, TileType};
+use super::components::{Ai, AiState, Fighter, InBackpack, Name, Position, Renderable};
+use super::gamelog::GameLog;
+use super::map::Map;
+use super::targeting::{line_of_fire, valid_targets};
+use super::tile_registry::tile_def;
+use super::MainMenuSelection;
 use bracket_lib::prelude::*;
 use specs::prelude::*;
+use std::cmp::Reverse;
 
 pub fn draw_map(ecs: &World, ctx: &mut BTerm) {
     let map = ecs.fetch::<Map>();
     for (idx, tile) in map.tiles.iter().enumerate() {
         let (x, y) = map.idx_xy(idx);
         if map.explored[idx] {
-            let mut fg_color = RGB::named(DARK_BLUE);
-            if map.visible[idx] {
-                fg_color = RGB::named(WHITE);
-            }
-            match tile {
-                TileType::Floor => {
-                    ctx.set(x, y, fg_color, RGB::named(BLACK), to_cp437('.'));
-                }
-                TileType::Wall => {
-                    ctx.set(x, y, fg_color, RGB::named(BLACK), to_cp437('#'));
-                }
-            }
+            let def = tile_def(*tile);
+            let fg_color = if map.visible[idx] {
+                def.visible
+            } else {
+                def.remembered
+            };
+            ctx.set(x, y, fg_color, RGB::named(BLACK), def.glyph);
         }
     }
 }
@@ -29,10 +29,453 @@ pub fn draw_entities(ecs: &World, ctx: &mut BTerm) {
     let positions = ecs.read_storage::<Position>();
     let renderables = ecs.read_storage::<Renderable>();
 
-    for (pos, renderable) in (&positions, &renderables).join() {
+    // Draw the entities with the highest render order first, so that the rest are drawn on top
+    let mut to_draw = (&positions, &renderables).join().collect::<Vec<_>>();
+    to_draw.sort_by_key(|(_pos, renderable)| Reverse(renderable.render_order));
+    for (pos, renderable) in to_draw {
         let pos_idx = map.xy_idx(pos.x, pos.y);
         if map.visible[pos_idx] {
             ctx.set(pos.x, pos.y, renderable.fg, renderable.bg, renderable.glyph);
         }
     }
 }
+
+pub fn draw_game_over(ctx: &mut BTerm) {
+    let (width, height) = ctx.get_char_size();
+    let (width, height) = (width as i32, height as i32);
+    let box_width = 40;
+    let box_height = 6;
+    let x = (width - box_width) / 2;
+    let y = (height - box_height) / 2;
+    ctx.draw_box(
+        x,
+        y,
+        box_width,
+        box_height,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+    );
+    ctx.print_color_centered(y + 2, RGB::named(RED), RGB::named(BLACK), "You are dead!");
+    ctx.print_color_centered(
+        y + 4,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+        "Press R to restart or Escape to quit",
+    );
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum ItemMenuResult {
+    Cancel,
+    NoResponse,
+    Selected,
+}
+
+/// Draw the list of items the player is carrying, and return the one selected with its letter, if
+/// any
+pub fn show_inventory(ecs: &World, ctx: &mut BTerm) -> (ItemMenuResult, Option<Entity>) {
+    let player = ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let names = ecs.read_storage::<Name>();
+    let backpack = ecs.read_storage::<InBackpack>();
+
+    let items: Vec<(Entity, &Name)> = (&entities, &names, &backpack)
+        .join()
+        .filter(|(_ent, _name, item)| item.owner == *player)
+        .map(|(ent, name, _item)| (ent, name))
+        .collect();
+
+    let width = 31;
+    let height = items.len() as i32 + 3;
+    let x = 15;
+    let mut y = 25 - height / 2;
+    ctx.draw_box(x, y, width, height, RGB::named(WHITE), RGB::named(BLACK));
+    ctx.print_color(x + 3, y, RGB::named(YELLOW), RGB::named(BLACK), "Inventory");
+    ctx.print_color(
+        x + 3,
+        y + height,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        "Escape to close",
+    );
+
+    y += 2;
+    if items.is_empty() {
+        ctx.print(x + 2, y, "You are not carrying anything.");
+    }
+    for (i, (_ent, name)) in items.iter().enumerate() {
+        let letter = (b'a' + i as u8) as char;
+        ctx.print_color(
+            x + 2,
+            y,
+            RGB::named(YELLOW),
+            RGB::named(BLACK),
+            &format!("{})", letter),
+        );
+        ctx.print(x + 5, y, &name.name);
+        y += 1;
+    }
+
+    match ctx.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(VirtualKeyCode::Escape) => (ItemMenuResult::Cancel, None),
+        Some(key) => {
+            let selection = letter_to_option(key);
+            if selection >= 0 && (selection as usize) < items.len() {
+                (ItemMenuResult::Selected, Some(items[selection as usize].0))
+            } else {
+                (ItemMenuResult::NoResponse, None)
+            }
+        }
+    }
+}
+
+/// Highlight the tiles that can be targeted within the given range, along with the line of fire
+/// from the player to the cursor
+pub fn draw_targeting(ecs: &World, ctx: &mut BTerm, range: i32, cursor: Position) {
+    ctx.print_color(
+        5,
+        0,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        "Select target with the movement keys and Enter (Escape to cancel)",
+    );
+
+    let targets = valid_targets(ecs, range);
+    for pos in targets.iter() {
+        ctx.set_bg(pos.x, pos.y, RGB::named(DARK_BLUE));
+    }
+    for pos in line_of_fire(ecs, cursor).iter() {
+        ctx.set_bg(pos.x, pos.y, RGB::named(BLUE));
+    }
+
+    if targets.contains(&cursor) {
+        ctx.set_bg(cursor.x, cursor.y, RGB::named(CYAN));
+    } else {
+        ctx.set_bg(cursor.x, cursor.y, RGB::named(RED));
+    }
+}
+
+/// Draw the panel at the bottom of the screen, below the map, with the latest messages
+pub fn draw_log_panel(ecs: &World, ctx: &mut BTerm) {
+    let map = ecs.fetch::<Map>();
+    let log = ecs.fetch::<GameLog>();
+    let (width, height) = ctx.get_char_size();
+    let (width, height) = (width as i32, height as i32);
+    let panel_height = height - map.height - 1;
+    ctx.draw_box(
+        0,
+        map.height,
+        width - 1,
+        panel_height,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+    );
+
+    // Fill the panel from the bottom up, with the most recent message at the bottom
+    let mut y = map.height + panel_height - 1;
+    for entry in log.entries.iter().rev() {
+        if y <= map.height {
+            break;
+        }
+        ctx.print_color(2, y, entry.color, RGB::named(BLACK), &entry.text);
+        y -= 1;
+    }
+}
+
+/// Describe how hurt a fighter is
+fn health_descriptor(fighter: &Fighter) -> &'static str {
+    let ratio = fighter.hp as f32 / fighter.max_hp as f32;
+    if ratio >= 1.0 {
+        "unhurt"
+    } else if ratio > 0.75 {
+        "scratched"
+    } else if ratio > 0.5 {
+        "wounded"
+    } else if ratio > 0.25 {
+        "badly wounded"
+    } else {
+        "almost dead"
+    }
+}
+
+/// Draw a box next to the given map position listing the entities in it, if the player can see it
+pub fn draw_tooltip(ecs: &World, ctx: &mut BTerm, pos: Position) {
+    let map = ecs.fetch::<Map>();
+    let names = ecs.read_storage::<Name>();
+    let fighters = ecs.read_storage::<Fighter>();
+
+    if !map.in_bounds(Point::new(pos.x, pos.y)) {
+        return;
+    }
+    let idx = map.xy_idx(pos.x, pos.y);
+    if !map.visible[idx] {
+        return;
+    }
+
+    let lines: Vec<String> = map.tile_entities[idx]
+        .iter()
+        .filter_map(|ent| {
+            names.get(*ent).map(|name| match fighters.get(*ent) {
+                Some(fighter) => format!("{} ({})", name.name, health_descriptor(fighter)),
+                None => name.name.clone(),
+            })
+        })
+        .collect();
+    if lines.is_empty() {
+        return;
+    }
+
+    // Show the box to the right of the position, unless it doesn't fit there
+    let width = lines.iter().map(|line| line.len()).max().unwrap() as i32 + 3;
+    let x = if pos.x + width + 1 < map.width {
+        pos.x + 1
+    } else {
+        pos.x - width - 1
+    };
+    let y = pos.y.min(map.height - lines.len() as i32 - 2).max(0);
+    ctx.draw_box(
+        x,
+        y,
+        width,
+        lines.len() as i32 + 1,
+        RGB::named(WHITE),
+        RGB::named(GRAY),
+    );
+    for (i, line) in lines.iter().enumerate() {
+        ctx.print_color(
+            x + 2,
+            y + 1 + i as i32,
+            RGB::named(WHITE),
+            RGB::named(GRAY),
+            line,
+        );
+    }
+}
+
+/// Return the colour that represents an AI state in the debug overlay
+fn ai_state_color(state: AiState) -> RGB {
+    match state {
+        AiState::Idle => RGB::named(GRAY),
+        AiState::Wander => RGB::named(BLUE),
+        AiState::Hunt => RGB::named(RED),
+        AiState::Search => RGB::named(ORANGE),
+        AiState::Flee => RGB::named(MAGENTA),
+    }
+}
+
+/// Debug overlay that shows the AI state of every monster, even the ones out of sight, as the
+/// background of its tile, and where they remember seeing the player with an 'x'
+pub fn draw_ai_overlay(ecs: &World, ctx: &mut BTerm) {
+    let positions = ecs.read_storage::<Position>();
+    let ais = ecs.read_storage::<Ai>();
+    let renderables = ecs.read_storage::<Renderable>();
+
+    for (pos, ai, renderable) in (&positions, &ais, renderables.maybe()).join() {
+        let color = ai_state_color(ai.state);
+        if let Some(last_pos) = ai.last_known_player_pos {
+            ctx.set(
+                last_pos.x,
+                last_pos.y,
+                color,
+                RGB::named(BLACK),
+                to_cp437('x'),
+            );
+        }
+        let glyph = renderable.map_or(to_cp437('?'), |r| r.glyph);
+        ctx.set(pos.x, pos.y, RGB::named(BLACK), color, glyph);
+    }
+
+    let mut x = 40;
+    for state in [
+        AiState::Idle,
+        AiState::Wander,
+        AiState::Hunt,
+        AiState::Search,
+        AiState::Flee,
+    ] {
+        let label = format!("{:?}", state);
+        ctx.print_color(x, 0, RGB::named(BLACK), ai_state_color(state), &label);
+        x += label.len() as i32 + 1;
+    }
+}
+
+/// Highlight the cursor of the look mode and describe what's under it
+pub fn draw_look(ecs: &World, ctx: &mut BTerm, cursor: Position) {
+    ctx.print_color(
+        5,
+        0,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        "Look around with the movement keys (T to travel there, Escape to finish)",
+    );
+    ctx.set_bg(cursor.x, cursor.y, RGB::named(CYAN));
+    draw_tooltip(ecs, ctx, cursor);
+}
+
+/// Draw the player status on the top border of the log panel
+pub fn draw_hud(ecs: &World, ctx: &mut BTerm) {
+    let map = ecs.fetch::<Map>();
+    let log = ecs.fetch::<GameLog>();
+    let player = ecs.fetch::<Entity>();
+    let fighters = ecs.read_storage::<Fighter>();
+    let (width, _height) = ctx.get_char_size();
+    let width = width as i32;
+    let y = map.height;
+
+    if let Some(fighter) = fighters.get(*player) {
+        // The lower the health, the more alarming the color
+        let ratio = fighter.hp as f32 / fighter.max_hp as f32;
+        let color = if ratio > 0.5 {
+            RGB::named(GREEN)
+        } else if ratio > 0.25 {
+            RGB::named(YELLOW)
+        } else {
+            RGB::named(RED)
+        };
+        let health = format!(" HP: {} / {} ", fighter.hp, fighter.max_hp);
+        ctx.print_color(2, y, color, RGB::named(BLACK), &health);
+        ctx.draw_bar_horizontal(
+            2 + health.len() as i32,
+            y,
+            20,
+            fighter.hp.max(0),
+            fighter.max_hp,
+            color,
+            RGB::named(BLACK),
+        );
+        ctx.print_color(
+            24 + health.len() as i32,
+            y,
+            RGB::named(WHITE),
+            RGB::named(BLACK),
+            &format!(" ATK: {}  DEF: {} ", fighter.attack, fighter.defense),
+        );
+    }
+
+    let status = format!(" Depth: {}  Turn: {} ", map.depth, log.turn);
+    ctx.print_color(
+        width - 2 - status.len() as i32,
+        y,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        &status,
+    );
+}
+
+/// Draw the whole message history, scrolled up by the given number of messages. Returns the new
+/// scroll, or None when the history is closed.
+pub fn show_log_history(ecs: &World, ctx: &mut BTerm, scroll: usize) -> Option<usize> {
+    let log = ecs.fetch::<GameLog>();
+    let (width, height) = ctx.get_char_size();
+    let (width, height) = (width as i32, height as i32);
+    ctx.draw_box(
+        0,
+        0,
+        width - 1,
+        height - 1,
+        RGB::named(WHITE),
+        RGB::named(BLACK),
+    );
+    ctx.print_color(
+        3,
+        0,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        "Message history",
+    );
+    ctx.print_color(
+        3,
+        height - 1,
+        RGB::named(YELLOW),
+        RGB::named(BLACK),
+        "Up/Down or PageUp/PageDown to scroll, Escape to close",
+    );
+
+    let lines = (height - 2) as usize;
+    let max_scroll = log.entries.len().saturating_sub(lines);
+    let scroll = scroll.min(max_scroll);
+    let last = log.entries.len() - scroll;
+    let first = last.saturating_sub(lines);
+    for (i, entry) in log.entries[first..last].iter().enumerate() {
+        let y = 1 + i as i32;
+        ctx.print_color(
+            2,
+            y,
+            RGB::named(GRAY),
+            RGB::named(BLACK),
+            &format!("[{:>5}]", entry.turn),
+        );
+        ctx.print_color(10, y, entry.color, RGB::named(BLACK), &entry.text);
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) | Some(VirtualKeyCode::M) => None,
+        Some(VirtualKeyCode::Up) | Some(VirtualKeyCode::K) | Some(VirtualKeyCode::Numpad8) => {
+            Some((scroll + 1).min(max_scroll))
+        }
+        Some(VirtualKeyCode::Down) | Some(VirtualKeyCode::J) | Some(VirtualKeyCode::Numpad2) => {
+            Some(scroll.saturating_sub(1))
+        }
+        Some(VirtualKeyCode::PageUp) => Some((scroll + lines).min(max_scroll)),
+        Some(VirtualKeyCode::PageDown) => Some(scroll.saturating_sub(lines)),
+        _ => Some(scroll),
+    }
+}
+
+#[derive(PartialEq, Copy, Clone)]
+pub enum MainMenuResult {
+    NoSelection { selected: MainMenuSelection },
+    Selected { selected: MainMenuSelection },
+}
+
+/// Draw the main menu with the given entry highlighted, and handle moving between the entries.
+/// Continue can only be selected if there is a saved game.
+pub fn show_main_menu(
+    ctx: &mut BTerm,
+    selected: MainMenuSelection,
+    can_continue: bool,
+) -> MainMenuResult {
+    let mut entries = vec![MainMenuSelection::NewGame];
+    if can_continue {
+        entries.push(MainMenuSelection::Continue);
+    }
+    entries.push(MainMenuSelection::Quit);
+    // The selection may no longer be available, e.g. if the save file has been deleted
+    let mut current = entries.iter().position(|e| *e == selected).unwrap_or(0);
+
+    ctx.print_color_centered(15, RGB::named(YELLOW), RGB::named(BLACK), "Roguelike Test");
+    let options = [
+        (MainMenuSelection::NewGame, "New Game"),
+        (MainMenuSelection::Continue, "Continue"),
+        (MainMenuSelection::Quit, "Quit"),
+    ];
+    for (i, (entry, text)) in options.iter().enumerate() {
+        let fg = if !entries.contains(entry) {
+            RGB::named(DARK_GRAY)
+        } else if *entry == entries[current] {
+            RGB::named(MAGENTA)
+        } else {
+            RGB::named(WHITE)
+        };
+        ctx.print_color_centered(20 + 2 * i as i32, fg, RGB::named(BLACK), text);
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Up) | Some(VirtualKeyCode::K) | Some(VirtualKeyCode::Numpad8) => {
+            current = (current + entries.len() - 1) % entries.len();
+        }
+        Some(VirtualKeyCode::Down) | Some(VirtualKeyCode::J) | Some(VirtualKeyCode::Numpad2) => {
+            current = (current + 1) % entries.len();
+        }
+        Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::NumpadEnter) => {
+            return MainMenuResult::Selected {
+                selected: entries[current],
+            };
+        }
+        _ => {}
+    }
+    MainMenuResult::NoSelection {
+        selected: entries[current],
+    }
+}
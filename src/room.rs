@@ -1,5 +1,7 @@
+use serde::{Deserialize, Serialize};
+
 /// For now a room is just a rectangle
-#[derive(Copy, Clone, PartialEq)]
+#[derive(Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Room {
     pub x0: i32,
     pub y0: i32,
@@ -0,0 +1,236 @@
+use crate::components::*;
+use crate::empty_world;
+use crate::gamelog::GameLog;
+use crate::map::Map;
+use crate::map_management_system::MapManagementSystem;
+use bracket_lib::prelude::*;
+use serde::{Deserialize, Serialize};
+use specs::error::NoError;
+use specs::prelude::*;
+use specs::saveload::{
+    DeserializeComponents, SerializeComponents, SimpleMarker, SimpleMarkerAllocator,
+};
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+pub const SAVE_FILE: &str = "savegame.json";
+
+/// Version of the save file format. Bump it whenever the saved data changes in an incompatible way.
+const SAVE_VERSION: u32 = 8;
+
+#[derive(Debug)]
+pub enum SaveLoadError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Version(u32),
+    NoPlayer,
+}
+
+impl fmt::Display for SaveLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveLoadError::Io(e) => write!(f, "I/O error: {}", e),
+            SaveLoadError::Json(e) => write!(f, "malformed save file: {}", e),
+            SaveLoadError::Version(v) => write!(
+                f,
+                "save file version {} is not supported (expected {})",
+                v, SAVE_VERSION
+            ),
+            SaveLoadError::NoPlayer => write!(f, "there is no player in the save file"),
+        }
+    }
+}
+
+impl From<io::Error> for SaveLoadError {
+    fn from(e: io::Error) -> Self {
+        SaveLoadError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for SaveLoadError {
+    fn from(e: serde_json::Error) -> Self {
+        SaveLoadError::Json(e)
+    }
+}
+
+/// Call the given macro with the list of all the component types that are saved, after the given
+/// arguments. Components have to be added here to be saved.
+macro_rules! with_saved_components {
+    ($macro:ident, $($args:expr),*) => {
+        $macro!(
+            $($args),*;
+            Position,
+            Name,
+            Renderable,
+            Player,
+            Vision,
+            Monster,
+            Blocking,
+            Fighter,
+            LeavesCorpse,
+            Item,
+            InBackpack,
+            Consumable,
+            ProvidesHealing,
+            InflictsDamage,
+            Ranged,
+            Energy,
+            MyTurn,
+            Ai
+        )
+    };
+}
+
+/// Serialize each component type separately, storing the result under the type name
+macro_rules! serialize_individually {
+    ($ecs:expr, $components:expr; $($type:ty),*) => {
+        $(
+            let value = SerializeComponents::<NoError, SimpleMarker<SerializeMe>>::serialize(
+                &($ecs.read_storage::<$type>(),),
+                &$ecs.entities(),
+                &$ecs.read_storage::<SimpleMarker<SerializeMe>>(),
+                serde_json::value::Serializer,
+            )?;
+            $components.insert(String::from(stringify!($type)), value);
+        )*
+    };
+}
+
+/// Deserialize each component type from the value stored under its type name, if there is any
+macro_rules! deserialize_individually {
+    ($ecs:expr, $components:expr; $($type:ty),*) => {
+        $(
+            if let Some(value) = $components.remove(stringify!($type)) {
+                DeserializeComponents::<NoError, _>::deserialize(
+                    &mut ($ecs.write_storage::<$type>(),),
+                    &$ecs.entities(),
+                    &mut $ecs.write_storage::<SimpleMarker<SerializeMe>>(),
+                    &mut $ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>(),
+                    value,
+                )?;
+            }
+        )*
+    };
+}
+
+/// Everything that is written to the save file
+#[derive(Serialize, Deserialize)]
+struct SaveGame {
+    version: u32,
+    // The RNG can't be saved, so instead it is reseeded with this both when saving and loading
+    rng_seed: u64,
+    map: Map,
+    player_pos: Position,
+    log: GameLog,
+    // The entities with each component type, by component name
+    components: serde_json::Map<String, serde_json::Value>,
+}
+
+pub fn save_exists() -> bool {
+    Path::new(SAVE_FILE).exists()
+}
+
+pub fn delete_save() -> Result<(), SaveLoadError> {
+    if save_exists() {
+        fs::remove_file(SAVE_FILE)?;
+    }
+    Ok(())
+}
+
+/// Save the map, the player position and all marked entities to the save file
+pub fn save_game(ecs: &mut World) -> Result<(), SaveLoadError> {
+    save_game_to(ecs, SAVE_FILE)
+}
+
+fn save_game_to(ecs: &mut World, path: &str) -> Result<(), SaveLoadError> {
+    let rng_seed = {
+        let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+        let rng_seed = rng.next_u64();
+        *rng = RandomNumberGenerator::seeded(rng_seed);
+        rng_seed
+    };
+
+    let mut components = serde_json::Map::new();
+    with_saved_components!(serialize_individually, ecs, components);
+
+    let save = SaveGame {
+        version: SAVE_VERSION,
+        rng_seed,
+        map: (*ecs.fetch::<Map>()).clone(),
+        player_pos: *ecs.fetch::<Position>(),
+        log: (*ecs.fetch::<GameLog>()).clone(),
+        components,
+    };
+    let writer = File::create(path)?;
+    serde_json::to_writer(writer, &save)?;
+    Ok(())
+}
+
+/// Replace the current world with the one in the save file. If the file can't be read, was written
+/// by an incompatible version or is missing anything, the world is left untouched.
+pub fn load_game(ecs: &mut World) -> Result<(), SaveLoadError> {
+    load_game_from(ecs, SAVE_FILE)
+}
+
+fn load_game_from(ecs: &mut World, path: &str) -> Result<(), SaveLoadError> {
+    let mut save: SaveGame = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if save.version != SAVE_VERSION {
+        return Err(SaveLoadError::Version(save.version));
+    }
+
+    // Load everything into a new world, so that nothing is lost if it fails halfway
+    let mut loaded = empty_world();
+    with_saved_components!(deserialize_individually, loaded, save.components);
+
+    let mut map = save.map;
+    map.reset_entity_data();
+    loaded.insert(map);
+    loaded.insert(save.player_pos);
+    loaded.insert(save.log);
+    loaded.insert(RandomNumberGenerator::seeded(save.rng_seed));
+    let player = {
+        let entities = loaded.entities();
+        let players = loaded.read_storage::<Player>();
+        let (player, _) = (&entities, &players)
+            .join()
+            .next()
+            .ok_or(SaveLoadError::NoPlayer)?;
+        player
+    };
+    loaded.insert(player);
+
+    // Recompute the map data that depends on the entities
+    MapManagementSystem {}.run_now(&loaded);
+    *ecs = loaded;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::build_world;
+    use crate::tests::{load_resources, snapshot, wait_turns};
+
+    #[test]
+    fn saved_game_loads_the_same() {
+        load_resources();
+        let path = std::env::temp_dir().join("roguelike-rust-test-savegame.json");
+        let path = path
+            .to_str()
+            .expect("The temporary directory is not valid UTF-8");
+
+        let mut ecs = wait_turns(build_world(7), 10);
+        save_game_to(&mut ecs, path).expect("Unable to save the game");
+        let mut loaded = World::new();
+        load_game_from(&mut loaded, path).expect("Unable to load the game");
+        fs::remove_file(path).expect("Unable to delete the saved game");
+        assert!(snapshot(&ecs) == snapshot(&loaded));
+
+        // Anything left out of the save would make the game go on differently
+        let ecs = wait_turns(ecs, 30);
+        let loaded = wait_turns(loaded, 30);
+        assert!(snapshot(&ecs) == snapshot(&loaded));
+    }
+}
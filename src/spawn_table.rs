@@ -0,0 +1,46 @@
+use crate::raws::raws;
+use bracket_lib::prelude::*;
+
+/// Names of monsters and items to spawn, each one picked with a chance proportional to its weight
+pub struct SpawnTable {
+    entries: Vec<(String, i32)>,
+    total_weight: i32,
+}
+
+impl SpawnTable {
+    /// Return the table with the entries of the raws that can be spawned at the given depth
+    pub fn for_depth(depth: i32) -> Self {
+        let mut table = Self {
+            entries: Vec::new(),
+            total_weight: 0,
+        };
+        for entry in raws().spawn_table.iter() {
+            let deep_enough = depth >= entry.min_depth;
+            let shallow_enough = entry.max_depth.is_none_or(|max_depth| depth <= max_depth);
+            if deep_enough && shallow_enough {
+                table.add(&entry.name, entry.weight);
+            }
+        }
+        table
+    }
+
+    pub fn add(&mut self, name: &str, weight: i32) {
+        self.entries.push((String::from(name), weight));
+        self.total_weight += weight;
+    }
+
+    /// Pick a random entry, or None if the table is empty
+    pub fn roll(&self, rng: &mut RandomNumberGenerator) -> Option<&str> {
+        if self.total_weight == 0 {
+            return None;
+        }
+        let mut roll = rng.range(0, self.total_weight);
+        for (name, weight) in self.entries.iter() {
+            if roll < *weight {
+                return Some(name);
+            }
+            roll -= weight;
+        }
+        None
+    }
+}
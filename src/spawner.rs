@@ -0,0 +1,170 @@
+use crate::components::*;
+use crate::initiative_system::{ACTION_COST, NORMAL_SPEED};
+use crate::map::Map;
+use crate::raws::{raws, ItemTemplate, MonsterTemplate};
+use crate::spawn_table::SpawnTable;
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+use specs::saveload::{MarkedBuilder, SimpleMarker};
+
+/// Regions get between 1 and this many spawns, plus one more every three levels
+const MAX_SPAWNS_PER_REGION: i32 = 3;
+
+/// Create the player entity at the given position
+pub fn player(ecs: &mut World, pos: Position) -> Entity {
+    ecs.create_entity()
+        .with(pos)
+        .with(Name {
+            name: String::from("Hero"),
+        })
+        .with(Renderable {
+            glyph: to_cp437('@'),
+            fg: RGB::named(YELLOW),
+            bg: RGB::named(BLACK),
+            render_order: 0,
+        })
+        .with(Player {})
+        .with(Vision {
+            visible: TileSet::default(),
+            range: 8,
+            recompute: true,
+        })
+        .with(Fighter {
+            max_hp: 30,
+            hp: 30,
+            attack: 5,
+            defense: 2,
+        })
+        // The player starts with enough energy to act right away
+        .with(Energy {
+            speed: NORMAL_SPEED,
+            energy: ACTION_COST,
+        })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+/// Create the monster or item with the given name in the raws at the given position of the current
+/// map. Monsters get tougher the deeper the map is. Returns None if there is no such monster or item.
+pub fn spawn_named(ecs: &mut World, name: &str, pos: Position) -> Option<Entity> {
+    let raws = raws();
+    let (ent, blocking) = if let Some(template) = raws.monsters.get(name) {
+        (spawn_monster(ecs, template, pos), template.blocking)
+    } else {
+        (spawn_item(ecs, raws.items.get(name)?, pos), false)
+    };
+    ecs.write_resource::<Map>().add_entity(ent, pos, blocking);
+    Some(ent)
+}
+
+fn spawn_monster(ecs: &mut World, template: &MonsterTemplate, pos: Position) -> Entity {
+    let depth = ecs.fetch::<Map>().depth;
+    let max_hp = template.hp + 2 * (depth - 1);
+    let mut builder = ecs
+        .create_entity()
+        .with(pos)
+        .with(Name {
+            name: template.name.clone(),
+        })
+        .with(Renderable {
+            glyph: template.glyph,
+            fg: template.fg,
+            bg: template.bg,
+            render_order: 1,
+        })
+        .with(Vision {
+            visible: TileSet::default(),
+            range: template.vision_range,
+            recompute: true,
+        })
+        .with(Monster {})
+        .with(Ai {
+            state: AiState::Wander,
+            last_known_player_pos: None,
+            search_turns: 0,
+        })
+        .with(Fighter {
+            max_hp,
+            hp: max_hp,
+            attack: template.attack + (depth - 1) / 2,
+            defense: template.defense + (depth - 1) / 3,
+        })
+        .with(Energy {
+            speed: template.speed,
+            energy: 0,
+        });
+    if template.blocking {
+        builder = builder.with(Blocking {});
+    }
+    if template.leaves_corpse {
+        builder = builder.with(LeavesCorpse {});
+    }
+    builder.marked::<SimpleMarker<SerializeMe>>().build()
+}
+
+fn spawn_item(ecs: &mut World, template: &ItemTemplate, pos: Position) -> Entity {
+    let mut builder = ecs
+        .create_entity()
+        .with(pos)
+        .with(Name {
+            name: template.name.clone(),
+        })
+        .with(Renderable {
+            glyph: template.glyph,
+            fg: template.fg,
+            bg: template.bg,
+            render_order: 2,
+        })
+        .with(Item {});
+    if template.consumable {
+        builder = builder.with(Consumable {});
+    }
+    if let Some(amount) = template.healing {
+        builder = builder.with(ProvidesHealing { amount });
+    }
+    if let Some(amount) = template.damage {
+        builder = builder.with(InflictsDamage { amount });
+    }
+    if let Some(range) = template.range {
+        builder = builder.with(Ranged { range });
+    }
+    builder.marked::<SimpleMarker<SerializeMe>>().build()
+}
+
+/// Spawn a random number of monsters and items from the spawn table of the current depth in each
+/// spawn region, at free tiles away from the player
+pub fn populate_regions(ecs: &mut World, regions: &[Vec<Position>]) {
+    let depth = ecs.fetch::<Map>().depth;
+    let table = SpawnTable::for_depth(depth);
+    let player_pos = *ecs.fetch::<Position>();
+
+    for region in regions.iter() {
+        let spawns = ecs
+            .write_resource::<RandomNumberGenerator>()
+            .range(1, MAX_SPAWNS_PER_REGION + depth / 3 + 1);
+        for _ in 0..spawns {
+            // Nothing is spawned on top of the player or anything else
+            let free_tiles: Vec<Position> = {
+                let map = ecs.fetch::<Map>();
+                region
+                    .iter()
+                    .filter(|pos| {
+                        let idx = map.xy_idx(pos.x, pos.y);
+                        **pos != player_pos
+                            && !map.blocked[idx]
+                            && map.tile_entities[idx].is_empty()
+                    })
+                    .copied()
+                    .collect()
+            };
+            let (name, pos) = {
+                let mut rng = ecs.write_resource::<RandomNumberGenerator>();
+                match (table.roll(&mut rng), rng.random_slice_entry(&free_tiles)) {
+                    (Some(name), Some(pos)) => (name, *pos),
+                    _ => break,
+                }
+            };
+            spawn_named(ecs, name, pos);
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::components::{Position, Vision};
+use crate::gamelog::GameLog;
+use crate::map::Map;
+use crate::player::{movement_direction, start_travel, use_item};
+use crate::TurnState;
+use bracket_lib::prelude::*;
+use specs::prelude::*;
+
+/// Return the tiles the player can see within the given range, which are the valid targets
+pub fn valid_targets(ecs: &World, range: i32) -> Vec<Position> {
+    let player = ecs.fetch::<Entity>();
+    let player_pos = ecs.fetch::<Position>();
+    let map = ecs.fetch::<Map>();
+    let visions = ecs.read_storage::<Vision>();
+
+    let mut targets = Vec::new();
+    if let Some(vision) = visions.get(*player) {
+        for pos in vision
+            .visible
+            .iter()
+            .map(|idx| Position::from(map.idx_xy(*idx)))
+        {
+            let distance = DistanceAlg::Pythagoras.distance2d(
+                Point::new(player_pos.x, player_pos.y),
+                Point::new(pos.x, pos.y),
+            );
+            if distance <= range as f32 {
+                targets.push(pos);
+            }
+        }
+    }
+    targets
+}
+
+/// Return the tiles in the line of fire from the player to the target, excluding the player's tile
+pub fn line_of_fire(ecs: &World, target: Position) -> Vec<Position> {
+    let player_pos = ecs.fetch::<Position>();
+    line2d(
+        LineAlg::Bresenham,
+        Point::new(player_pos.x, player_pos.y),
+        Point::new(target.x, target.y),
+    )
+    .iter()
+    .map(|p| Position::new(p.x, p.y))
+    .filter(|pos| *pos != *player_pos)
+    .collect()
+}
+
+/// Handle input while targeting with the given item: the cursor moves with the movement keys, and
+/// the target is confirmed with Enter (or a mouse click) or the targeting is cancelled with Escape
+pub fn targeting_input(
+    ecs: &mut World,
+    ctx: &mut BTerm,
+    range: i32,
+    item: Entity,
+    cursor: Position,
+) -> TurnState {
+    let targeting = TurnState::ShowTargeting {
+        range,
+        item,
+        cursor,
+    };
+
+    if ctx.left_click {
+        let (mouse_x, mouse_y) = ctx.mouse_pos();
+        let mouse_pos = Position::new(mouse_x, mouse_y);
+        if valid_targets(ecs, range).contains(&mouse_pos) {
+            return use_item(ecs, item, Some(mouse_pos));
+        }
+    }
+
+    match ctx.key {
+        None => targeting,
+        Some(VirtualKeyCode::Escape) => TurnState::Paused,
+        Some(VirtualKeyCode::Return) | Some(VirtualKeyCode::NumpadEnter) => {
+            if valid_targets(ecs, range).contains(&cursor) {
+                use_item(ecs, item, Some(cursor))
+            } else {
+                ecs.write_resource::<GameLog>()
+                    .info("That target is out of sight or range.");
+                targeting
+            }
+        }
+        Some(key) => TurnState::ShowTargeting {
+            range,
+            item,
+            cursor: move_cursor(ecs, cursor, key),
+        },
+    }
+}
+
+/// Handle input in look mode, where the cursor moves with the movement keys to inspect the map,
+/// until Escape or Enter is pressed or T is pressed to travel to the cursor
+pub fn look_input(ecs: &mut World, ctx: &mut BTerm, cursor: Position) -> TurnState {
+    match ctx.key {
+        None => TurnState::Look { cursor },
+        Some(VirtualKeyCode::T) => start_travel(ecs, cursor),
+        Some(VirtualKeyCode::Escape)
+        | Some(VirtualKeyCode::Return)
+        | Some(VirtualKeyCode::NumpadEnter) => TurnState::Paused,
+        Some(key) => TurnState::Look {
+            cursor: move_cursor(ecs, cursor, key),
+        },
+    }
+}
+
+/// Return the new position of a cursor after pressing a key, which only moves it if it's a movement
+/// key and the cursor stays inside the map
+fn move_cursor(ecs: &World, cursor: Position, key: VirtualKeyCode) -> Position {
+    let map = ecs.fetch::<Map>();
+    if let Some((dx, dy)) = movement_direction(key) {
+        let new_cursor = cursor + Position::new(dx, dy);
+        if map.in_bounds(Point::new(new_cursor.x, new_cursor.y)) {
+            return new_cursor;
+        }
+    }
+    cursor
+}
@@ -0,0 +1,228 @@
+use crate::map::TileType;
+use bracket_lib::prelude::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::sync::OnceLock;
+
+pub const TILES_FILE: &str = "res/tiles.json";
+
+/// All the tile types, which must be defined in the tiles file
+const TILE_TYPES: [TileType; 5] = [
+    TileType::Wall,
+    TileType::Floor,
+    TileType::DownStairs,
+    TileType::DoorClosed,
+    TileType::DoorOpen,
+];
+
+static TILES: OnceLock<HashMap<TileType, TileDef>> = OnceLock::new();
+
+/// How a tile type is drawn and how it behaves
+pub struct TileDef {
+    pub glyph: u8,
+    /// Colour of the tile while the player sees it
+    pub visible: RGB,
+    /// Colour of the tile when it's explored but out of sight
+    pub remembered: RGB,
+    pub walkable: bool,
+    pub opaque: bool,
+    /// Multiplier of the cost of moving into the tile, used by pathfinding
+    pub cost: f32,
+}
+
+/// A tile definition as written in the tiles file
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RawTileDef {
+    glyph: char,
+    visible: String,
+    remembered: String,
+    walkable: bool,
+    opaque: bool,
+    cost: f32,
+}
+
+#[derive(Debug)]
+pub enum TileRegistryError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    Missing(TileType),
+    Color(TileType, String),
+    Cost(TileType, f32),
+}
+
+impl fmt::Display for TileRegistryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TileRegistryError::Io(e) => write!(f, "I/O error: {}", e),
+            TileRegistryError::Json(e) => write!(f, "malformed tiles file: {}", e),
+            TileRegistryError::Missing(tile) => write!(f, "tile {:?} is not defined", tile),
+            TileRegistryError::Color(tile, color) => write!(
+                f,
+                "tile {:?} has colour {:?}, expected an HTML colour code like \"#00ff00\"",
+                tile, color
+            ),
+            TileRegistryError::Cost(tile, cost) => write!(
+                f,
+                "tile {:?} has cost {}, expected a positive number",
+                tile, cost
+            ),
+        }
+    }
+}
+
+impl From<io::Error> for TileRegistryError {
+    fn from(e: io::Error) -> Self {
+        TileRegistryError::Io(e)
+    }
+}
+
+impl From<serde_json::Error> for TileRegistryError {
+    fn from(e: serde_json::Error) -> Self {
+        TileRegistryError::Json(e)
+    }
+}
+
+/// Parse and check the tile definitions from the contents of a tiles file
+fn parse_tiles(json: &str) -> Result<HashMap<TileType, TileDef>, TileRegistryError> {
+    let mut raw_tiles: HashMap<TileType, RawTileDef> = serde_json::from_str(json)?;
+    let mut tiles = HashMap::new();
+    for tile in TILE_TYPES.iter() {
+        let raw = raw_tiles
+            .remove(tile)
+            .ok_or(TileRegistryError::Missing(*tile))?;
+        let color = |code: &String| {
+            RGB::from_hex(code).map_err(|_| TileRegistryError::Color(*tile, code.clone()))
+        };
+        if !(raw.cost.is_finite() && raw.cost > 0.0) {
+            return Err(TileRegistryError::Cost(*tile, raw.cost));
+        }
+        tiles.insert(
+            *tile,
+            TileDef {
+                glyph: to_cp437(raw.glyph),
+                visible: color(&raw.visible)?,
+                remembered: color(&raw.remembered)?,
+                walkable: raw.walkable,
+                opaque: raw.opaque,
+                cost: raw.cost,
+            },
+        );
+    }
+    Ok(tiles)
+}
+
+/// Load the tile definitions from a file. It has to be done once at startup, before any map is
+/// used. Only the first successful load counts: the file is still read and checked on later calls,
+/// but the definitions loaded first are kept.
+pub fn load_tiles(path: &str) -> Result<(), TileRegistryError> {
+    let tiles = parse_tiles(&fs::read_to_string(path)?)?;
+    let _ = TILES.set(tiles);
+    Ok(())
+}
+
+/// Return the definition of a tile type
+pub fn tile_def(tile: TileType) -> &'static TileDef {
+    TILES
+        .get()
+        .expect("The tile definitions have not been loaded")
+        .get(&tile)
+        .expect("Every tile type is checked to be defined when loading")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    /// Return the definitions of every tile type, to be broken by each test
+    fn valid_tiles() -> Value {
+        let tile = json!({
+            "glyph": ".",
+            "visible": "#FFFFFF",
+            "remembered": "#00008B",
+            "walkable": true,
+            "opaque": false,
+            "cost": 1.0
+        });
+        json!({
+            "Wall": tile,
+            "Floor": tile,
+            "DownStairs": tile,
+            "DoorClosed": tile,
+            "DoorOpen": tile
+        })
+    }
+
+    fn parse_error(json: &str) -> String {
+        match parse_tiles(json) {
+            Ok(_) => panic!("The tiles were parsed without errors"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn tiles_file_is_valid() {
+        let json = fs::read_to_string(TILES_FILE).expect("Unable to read the tiles file");
+        assert!(parse_tiles(&json).is_ok());
+        assert!(parse_tiles(&valid_tiles().to_string()).is_ok());
+    }
+
+    #[test]
+    fn malformed_json() {
+        assert_eq!(
+            parse_error("{\"Wall\": {"),
+            "malformed tiles file: EOF while parsing an object at line 1 column 10"
+        );
+    }
+
+    #[test]
+    fn unknown_tile_type() {
+        let mut tiles = valid_tiles();
+        tiles["Lava"] = tiles["Floor"].clone();
+        assert!(parse_error(&tiles.to_string()).starts_with(
+            "malformed tiles file: unknown variant `Lava`, expected one of `Wall`, `Floor`"
+        ));
+    }
+
+    #[test]
+    fn unknown_field() {
+        let mut tiles = valid_tiles();
+        tiles["Floor"]["colour"] = json!("#FFFFFF");
+        assert!(parse_error(&tiles.to_string())
+            .starts_with("malformed tiles file: unknown field `colour`, expected one of"));
+    }
+
+    #[test]
+    fn missing_tile() {
+        let mut tiles = valid_tiles();
+        tiles.as_object_mut().unwrap().remove("DoorOpen");
+        assert_eq!(
+            parse_error(&tiles.to_string()),
+            "tile DoorOpen is not defined"
+        );
+    }
+
+    #[test]
+    fn invalid_colour() {
+        let mut tiles = valid_tiles();
+        tiles["Wall"]["remembered"] = json!("dark blue");
+        assert_eq!(
+            parse_error(&tiles.to_string()),
+            "tile Wall has colour \"dark blue\", expected an HTML colour code like \"#00ff00\""
+        );
+    }
+
+    #[test]
+    fn invalid_cost() {
+        let mut tiles = valid_tiles();
+        tiles["DoorClosed"]["cost"] = json!(0.0);
+        assert_eq!(
+            parse_error(&tiles.to_string()),
+            "tile DoorClosed has cost 0, expected a positive number"
+        );
+    }
+}
@@ -17,30 +17,37 @@ impl<'a> System<'a> for VisibilitySystem {
         for (ent, vision, pos) in (&entities, &mut vision, &pos).join() {
             if vision.recompute {
                 vision.recompute = false;
-                vision.visible.clear();
-                let mut visible_pts: Vec<Point> =
-                    field_of_view(Point::new(pos.x, pos.y), vision.range, &*map);
-                // Remove out of bounds tiles. For this, use the in_bounds method we get for free from
-                // implementing BaseMap
-                visible_pts.retain(|p| map.in_bounds(Point::new(p.x, p.y)));
-                vision.visible = visible_pts
-                    .iter()
-                    .map(|p| Position::new(p.x, p.y))
-                    .collect();
-
-                // Mark viewed tiles as explored if this is the player, and set visible tiles
-                // TODO: Improve performance
-                if let Some(_) = player.get(ent) {
-                    for t in map.visible.iter_mut() {
-                        *t = false;
-                    }
-                    for pos in &vision.visible {
-                        let idx = map.xy_idx(pos.x, pos.y);
-                        map.explored[idx] = true;
-                        map.visible[idx] = true;
-                    }
-                }
+                let fov = field_of_view(Point::new(pos.x, pos.y), vision.range, &*map);
+                set_visible_tiles(&mut map, vision, &fov, player.get(ent).is_some());
             }
         }
     }
 }
+
+/// Replace the visible tiles of an entity with the ones in its field of view. If it's the player,
+/// the visible and explored tiles of the map are updated too.
+pub fn set_visible_tiles(map: &mut Map, vision: &mut Vision, fov: &[Point], is_player: bool) {
+    // Only the tiles the player could see until now have to be hidden
+    if is_player {
+        for idx in vision.visible.iter() {
+            map.visible[*idx] = false;
+        }
+    }
+
+    vision.visible.clear();
+    for p in fov {
+        // Remove out of bounds tiles. For this, use the in_bounds method we get for free from
+        // implementing BaseMap
+        if map.in_bounds(*p) {
+            vision.visible.insert(map.point2d_to_index(*p));
+        }
+    }
+
+    // Mark viewed tiles as explored if this is the player, and set visible tiles
+    if is_player {
+        for idx in vision.visible.iter() {
+            map.explored[*idx] = true;
+            map.visible[*idx] = true;
+        }
+    }
+}